@@ -0,0 +1,191 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::event::{EventModel, EventTime};
+
+// TUIを使わずに予定を出力するときの形式
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum AgendaFormat {
+    Text,
+    Json,
+    Tsv,
+}
+
+#[derive(Debug, Serialize)]
+struct AgendaItem {
+    title: String,
+    start: String,
+    end: String,
+    calendar: String,
+    location: Option<String>,
+    all_day: bool,
+    tags: Vec<String>,
+}
+
+impl AgendaItem {
+    fn from_event(event: &EventModel) -> Option<Self> {
+        let start = event.start()?;
+        let end = event.end()?;
+        Some(AgendaItem {
+            title: event.title(),
+            start: Self::format_time(start),
+            end: Self::format_time(end),
+            calendar: event.calendar().name().to_string(),
+            location: event.location().map(str::to_string),
+            all_day: event.is_all_day(),
+            tags: event.tags().to_vec(),
+        })
+    }
+
+    fn format_time(time: EventTime) -> String {
+        match time {
+            EventTime::AllDay(date) => date.format("%Y-%m-%d").to_string(),
+            EventTime::DateTime(date_time) => date_time.to_rfc3339(),
+        }
+    }
+}
+
+// 複数カレンダーの予定をまとめて、終日予定 → 開始時刻順に並べる
+fn sorted(events: &[EventModel]) -> Vec<&EventModel> {
+    let mut events: Vec<&EventModel> = events.iter().filter(|e| e.start().is_some()).collect();
+    events.sort_by_key(|event| (!event.is_all_day(), event.start()));
+    events
+}
+
+pub fn render(events: &[EventModel], date: NaiveDate, format: AgendaFormat) -> Result<String> {
+    let events = sorted(events);
+    match format {
+        AgendaFormat::Text => Ok(render_text(&events, date)),
+        AgendaFormat::Json => {
+            let items: Vec<AgendaItem> = events
+                .iter()
+                .filter_map(|event| AgendaItem::from_event(event))
+                .collect();
+            Ok(serde_json::to_string_pretty(&items)? + "\n")
+        }
+        AgendaFormat::Tsv => Ok(render_tsv(&events)),
+    }
+}
+
+fn render_text(events: &[&EventModel], date: NaiveDate) -> String {
+    let mut output = format!("{}\n", date.format("%Y-%m-%d (%a)"));
+    if events.is_empty() {
+        output.push_str("  予定はありません\n");
+        return output;
+    }
+    for event in events {
+        let time = match (event.start(), event.end()) {
+            (Some(EventTime::DateTime(start)), Some(EventTime::DateTime(end))) => {
+                format!("{}-{}", start.format("%H:%M"), end.format("%H:%M"))
+            }
+            // 全角文字は表示幅が2なので「HH:MM-HH:MM」と同じ11桁になるよう手で揃える
+            _ => "終日       ".to_string(),
+        };
+        output.push_str(&format!(
+            "  {} {} [{}]",
+            time,
+            event.title(),
+            event.calendar().name()
+        ));
+        if let Some(location) = event.location() {
+            output.push_str(&format!(" @ {}", location));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn render_tsv(events: &[&EventModel]) -> String {
+    // TSVの区切りを壊さないようにタブと改行は空白に置き換える
+    let escape = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+    events
+        .iter()
+        .filter_map(|event| AgendaItem::from_event(event))
+        .map(|item| {
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                item.start,
+                item.end,
+                item.all_day,
+                escape(&item.calendar),
+                escape(&item.title),
+                escape(item.location.as_deref().unwrap_or_default())
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::Calendar;
+    use google_calendar3::api::{Event, EventDateTime};
+
+    fn timed_event(summary: &str, start: &str, end: &str) -> EventModel {
+        let parse = |s: &str| chrono::DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+        EventModel::new(
+            Event {
+                summary: Some(summary.to_string()),
+                start: Some(EventDateTime {
+                    date_time: Some(parse(start)),
+                    ..Default::default()
+                }),
+                end: Some(EventDateTime {
+                    date_time: Some(parse(end)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            Calendar::Primary,
+        )
+    }
+
+    fn all_day_event(summary: &str, date: NaiveDate) -> EventModel {
+        EventModel::new(
+            Event {
+                summary: Some(summary.to_string()),
+                start: Some(EventDateTime {
+                    date: Some(date),
+                    ..Default::default()
+                }),
+                end: Some(EventDateTime {
+                    date: Some(date.succ_opt().unwrap()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            Calendar::Private,
+        )
+    }
+
+    #[test]
+    fn test_render_sorts_all_day_first() {
+        let date = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        let events = vec![
+            timed_event(
+                "Lunch",
+                "2025-03-10T12:00:00+09:00",
+                "2025-03-10T13:00:00+09:00",
+            ),
+            timed_event(
+                "Standup",
+                "2025-03-10T09:00:00+09:00",
+                "2025-03-10T09:30:00+09:00",
+            ),
+            all_day_event("Holiday", date),
+        ];
+
+        let text = render(&events, date, AgendaFormat::Text).unwrap();
+        assert_eq!(
+            text,
+            "2025-03-10 (Mon)\n  終日        Holiday [Private]\n  09:00-09:30 Standup [Primary]\n  12:00-13:00 Lunch [Primary]\n"
+        );
+
+        let tsv = render(&events, date, AgendaFormat::Tsv).unwrap();
+        assert_eq!(
+            tsv.lines().next().unwrap(),
+            "2025-03-10\t2025-03-11\ttrue\tPrivate\tHoliday\t"
+        );
+    }
+}
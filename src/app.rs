@@ -0,0 +1,881 @@
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Asia::Tokyo;
+use chrono_tz::Tz;
+use ratatui::backend::Backend;
+use ratatui::crossterm;
+use ratatui::crossterm::event::{
+    Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::layout::Rect;
+use ratatui::Terminal;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::blank::Blank;
+use crate::chime::Chime;
+use crate::config::Config;
+use crate::dashboard::Dashboard;
+use crate::detail::DetailPopup;
+use crate::error::FetchError;
+use crate::event::{EventModel, EventTime, EventView};
+use crate::fetch::FetchEvents;
+use crate::process;
+use crate::reminder::{Reminder, Scheduler};
+use crate::rules;
+use crate::start_of_day;
+use crate::supervisor;
+use crate::theme::Theme;
+use crate::ui::{self, DayColumn};
+
+// メインループが受け取るイベント
+pub enum AppEvent {
+    Input(Event),
+    // Ctrl-C (SIGINT) による終了要求
+    Quit,
+    // 1分ごとの定期チェック
+    Tick,
+    FetchCompleted {
+        id: u64,
+        events: Vec<EventModel>,
+    },
+    FetchFailed {
+        id: u64,
+        error: FetchError,
+    },
+    // リマインダーとチャイムのために今日の分だけ取得した結果
+    TodayFetched {
+        date: NaiveDate,
+        result: Result<Vec<EventModel>, FetchError>,
+    },
+    // 取得のスレッドがpanicした。異常終了としてメインループを抜ける
+    FetchPanicked,
+}
+
+pub struct App {
+    config: Config,
+    theme: Theme,
+    fetcher: Arc<Mutex<dyn FetchEvents>>,
+    sender: UnboundedSender<AppEvent>,
+    events: Option<Vec<EventModel>>,
+    // 日ごとの列に表示する予定 (eventsのインデックスと表示用の情報)。取得のたびに作り直す
+    views: Vec<Vec<(usize, EventView)>>,
+    fetched_time: DateTime<Tz>,
+    // fetched_timeの日から何日分を表示しているか
+    days: u32,
+    // `w` で切り替える複数日表示の日数
+    multi_days: u32,
+    // 最後に発行した取得リクエストの番号。これより古い結果は捨てる
+    fetch_id: u64,
+    loading: bool,
+    fetch_error: Option<String>,
+    // リマインダーとチャイムに使う今日の予定と、その日付。別の日を表示している間も持っておく
+    today: Option<(NaiveDate, Vec<EventModel>)>,
+    // 今日の分だけの取得を待っているか
+    fetching_today: bool,
+    // 最後にキー操作があった時刻。一定時間操作がなければ今日の表示に戻す
+    last_input: Instant,
+    // `g` で日付入力中のときの入力途中の文字列
+    date_input: Option<String>,
+    // 選択中の予定 (displayed_events() のインデックス。列をまたいで通し番号)
+    selected: Option<usize>,
+    // 詳細ポップアップを開いているときのスクロール位置
+    detail_scroll: Option<u16>,
+    reminders: Scheduler,
+    // ヘッダーに出しているリマインダー。予定が始まるかキーが押されたら消す
+    reminder: Option<Reminder>,
+    chime: Chime,
+    blank: Blank,
+    // HTTPで同じ表示を見せるときの共有先
+    dashboard: Option<Dashboard>,
+    // 次の予定までの残り時間を何分単位で出すか
+    countdown_minutes: i64,
+}
+
+impl App {
+    pub fn new(
+        config: Config,
+        fetcher: impl FetchEvents + 'static,
+        now: DateTime<Tz>,
+        days: u32,
+    ) -> (Self, UnboundedReceiver<AppEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let app = App {
+            theme: Theme::from_config(&config.theme),
+            config,
+            fetcher: Arc::new(Mutex::new(fetcher)),
+            sender,
+            events: None,
+            views: Vec::new(),
+            fetched_time: now,
+            days,
+            multi_days: if days > 1 { days } else { 7 },
+            fetch_id: 0,
+            loading: false,
+            fetch_error: None,
+            today: None,
+            fetching_today: false,
+            last_input: Instant::now(),
+            date_input: None,
+            selected: None,
+            detail_scroll: None,
+            reminders: Scheduler::default(),
+            reminder: None,
+            chime: Chime::default(),
+            blank: Blank::default(),
+            dashboard: None,
+            countdown_minutes: 1,
+        };
+        (app, receiver)
+    }
+
+    pub fn sender(&self) -> UnboundedSender<AppEvent> {
+        self.sender.clone()
+    }
+
+    pub fn set_dashboard(&mut self, dashboard: Dashboard) {
+        self.dashboard = Some(dashboard);
+    }
+
+    // 電子ペーパーでは残り時間の表示が毎分変わって部分更新が続かないようにする
+    pub fn set_countdown_minutes(&mut self, minutes: i64) {
+        self.countdown_minutes = minutes;
+    }
+
+    fn dates(&self) -> Vec<NaiveDate> {
+        let first = self.fetched_time.date_naive();
+        (0..self.days)
+            .map(|i| first + chrono::Days::new(i.into()))
+            .collect()
+    }
+
+    // 日ごとの列に、その日のタイムラインに表示できる予定と表示用の情報を並べる
+    fn build_views(&mut self) {
+        let events = self.events.as_deref().unwrap_or_default();
+        self.views = self
+            .dates()
+            .into_iter()
+            .map(|date| {
+                events
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, event)| {
+                        let mut view = EventView::from_event(event.clone(), date).ok()?;
+                        view.color = self.theme.event_color(event);
+                        // タイトルのほか、場所やURLやタグからも中身が分かるので隠す予定では出さない
+                        if self.config.privacy.masks(event) {
+                            view.title = self.config.privacy.mask_title.clone();
+                        } else {
+                            view.details = self.config.event_box.details(event);
+                        }
+                        Some((i, view))
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+
+    fn columns(&self) -> Vec<Vec<(&EventModel, &EventView)>> {
+        let events = self.events.as_deref().unwrap_or_default();
+        self.views
+            .iter()
+            .map(|column| column.iter().map(|(i, view)| (&events[*i], view)).collect())
+            .collect()
+    }
+
+    fn displayed_events(&self) -> Vec<(&EventModel, &EventView)> {
+        self.columns().into_iter().flatten().collect()
+    }
+
+    // dateの日から表示日数分の予定の取得を別スレッドで始める。結果はAppEventで届く
+    pub fn fetch_date_events(&mut self, date: DateTime<Tz>) {
+        // 表示中と違う日に移るときは、古い予定が新しい日付の下に出ないよう消しておく
+        if date.date_naive() != self.fetched_time.date_naive() {
+            self.events = None;
+            self.views.clear();
+            self.selected = None;
+            self.detail_scroll = None;
+        }
+        self.fetched_time = date;
+        self.fetch_id += 1;
+        self.loading = true;
+
+        let id = self.fetch_id;
+        self.spawn_fetch(date, self.days, move |result| match result {
+            Ok(events) => AppEvent::FetchCompleted { id, events },
+            Err(error) => AppEvent::FetchFailed { id, error },
+        });
+    }
+
+    // 別の日を表示していて今日の予定を持っていなければ、今日の分だけ取得する
+    // 表示中の範囲に今日が入っていれば、その取得結果を使うので何もしない
+    fn fetch_today_events(&mut self, now: DateTime<Tz>) {
+        let date = now.date_naive();
+        if self.fetching_today
+            || self.dates().contains(&date)
+            || self.today.as_ref().is_some_and(|(today, _)| *today == date)
+        {
+            return;
+        }
+        self.fetching_today = true;
+        self.spawn_fetch(start_of_day(date), 1, move |result| {
+            AppEvent::TodayFetched { date, result }
+        });
+    }
+
+    // 通信はblockingなので、UIを止めないようにblocking用のスレッドで行う
+    fn spawn_fetch(
+        &self,
+        date: DateTime<Tz>,
+        days: u32,
+        to_event: impl FnOnce(Result<Vec<EventModel>, FetchError>) -> AppEvent + Send + 'static,
+    ) {
+        let fetcher = Arc::clone(&self.fetcher);
+        let sender = self.sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                fetcher
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .fetch_date_events(date, days)
+            }));
+            let event = match result {
+                Ok(result) => to_event(result),
+                Err(_) => AppEvent::FetchPanicked,
+            };
+            // 受信側が閉じているのは終了処理中なので無視してよい
+            let _ = sender.send(event);
+        });
+    }
+
+    // 1日表示と複数日表示を切り替える
+    fn toggle_days(&mut self) {
+        self.days = if self.days == 1 { self.multi_days } else { 1 };
+        self.build_views();
+        self.selected = None;
+        self.detail_scroll = None;
+        self.fetch_date_events(self.fetched_time);
+    }
+
+    fn select_next(&mut self) {
+        let count = self.displayed_events().len();
+        if count > 0 {
+            self.selected = Some(self.selected.map_or(0, |i| (i + 1).min(count - 1)));
+        }
+    }
+
+    fn select_previous(&mut self) {
+        if !self.displayed_events().is_empty() {
+            self.selected = Some(self.selected.map_or(0, |i| i.saturating_sub(1)));
+        }
+    }
+
+    fn is_showing_today(&self) -> bool {
+        self.dates()
+            .contains(&Utc::now().with_timezone(&Tokyo).date_naive())
+    }
+
+    pub fn show_date(&mut self, date: NaiveDate) {
+        self.fetch_date_events(start_of_day(date))
+    }
+
+    fn header(&self) -> String {
+        match &self.date_input {
+            Some(input) => format!("日付を入力 (YYYY-MM-DD): {}_", input),
+            None => {
+                let date = match self.dates().last() {
+                    Some(last) if self.days > 1 => format!(
+                        "{} ~ {}",
+                        self.fetched_time.format("%Y-%m-%d"),
+                        last.format("%Y-%m-%d")
+                    ),
+                    _ => self.fetched_time.format("%Y-%m-%d (%a)").to_string(),
+                };
+                let mut header = if self.is_showing_today() {
+                    format!("{} 今日", date)
+                } else {
+                    format!("{}  t:今日に戻る", date)
+                };
+                if self.loading {
+                    header.push_str("  読み込み中...");
+                } else if let Some(error) = &self.fetch_error {
+                    header.push_str(&format!("  取得に失敗しました: {}", error));
+                }
+                header
+            }
+        }
+    }
+
+    // 開始が近づいた予定があればヘッダーに出してコマンドを実行し、チャイムも鳴らす
+    // 端末のベルを鳴らすときはtrueを返す
+    fn check_reminders(&mut self, now: DateTime<Tz>) -> bool {
+        let events = today_events(&self.today, now).unwrap_or_default();
+        self.chime.check(&self.config.chime, events, now);
+        if self
+            .reminder
+            .as_ref()
+            .is_some_and(|reminder| reminder.start <= now)
+        {
+            self.reminder = None;
+        }
+        if !self.config.reminders.enabled {
+            return false;
+        }
+        let privacy = &self.config.privacy;
+        let reminders: Vec<Reminder> = self
+            .reminders
+            .due(
+                events,
+                |event| event.reminder_minutes(self.config.reminders.default_minutes),
+                now,
+            )
+            .into_iter()
+            .filter_map(|event| {
+                let Some(EventTime::DateTime(start)) = event.start() else {
+                    return None;
+                };
+                Some(Reminder {
+                    title: privacy.title(event),
+                    start,
+                    location: event
+                        .location()
+                        .filter(|_| !privacy.masks(event))
+                        .map(str::to_string),
+                })
+            })
+            .collect();
+        if let Some(command) = &self.config.reminders.command {
+            for reminder in &reminders {
+                reminder.run_command(command);
+            }
+        }
+        let fired = !reminders.is_empty();
+        // 複数あれば先に始まる方を出す
+        if let Some(reminder) = reminders.into_iter().min_by_key(|reminder| reminder.start) {
+            self.reminder = Some(reminder);
+        }
+        fired && self.config.reminders.bell
+    }
+
+    // 画面を消す・戻すタイミングで設定されたコマンドを実行する
+    fn update_blank(&mut self, now: DateTime<Tz>) {
+        let events = today_events(&self.today, now);
+        if let Some(command) = self.blank.update(&self.config.blank, events, now) {
+            process::spawn_detached(&mut process::shell(command));
+        }
+    }
+
+    // 表示中の日ごとの予定。selectedは列ごとのインデックスに直す
+    fn day_columns(&self, now: DateTime<Tz>) -> Vec<DayColumn> {
+        let mut offset = 0;
+        self.dates()
+            .into_iter()
+            .zip(self.columns())
+            .map(|(date, events)| {
+                let selected = self
+                    .selected
+                    .and_then(|i| i.checked_sub(offset))
+                    .filter(|i| *i < events.len());
+                offset += events.len();
+                DayColumn {
+                    date,
+                    events: events.into_iter().map(|(_, view)| view.clone()).collect(),
+                    // 今日以外の列には現在時刻の線を出さない
+                    now: (date == now.date_naive()).then_some(now),
+                    selected,
+                }
+            })
+            .collect()
+    }
+
+    pub fn redraw<B: Backend>(&self, terminal: &mut Terminal<B>) -> Result<()> {
+        let now = Utc::now().with_timezone(&Tokyo);
+        let columns = self.day_columns(now);
+        // 画面を消している間もブラウザからは見られるようにする
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.publish(&self.theme, self.header(), now, &columns);
+        }
+        if self.blank.is_blanked() {
+            return ui::render_blank(terminal);
+        }
+        let popup = self.detail_popup();
+        ui::render_ui(
+            terminal,
+            self.config.layout,
+            &self.theme,
+            now,
+            self.header(),
+            self.reminder.as_ref().map(|reminder| reminder.message(now)),
+            self.countdown_minutes,
+            columns,
+            popup,
+        )
+    }
+
+    fn detail_popup(&self) -> Option<DetailPopup> {
+        let scroll = self.detail_scroll?;
+        let (event, _) = self.displayed_events().into_iter().nth(self.selected?)?;
+        Some(DetailPopup::new(event, &self.config.privacy, scroll))
+    }
+
+    // 詳細ポップアップを最後の行より下へスクロールしないようにする
+    // 折り返した行数は画面の大きさで変わるので、キー操作やリサイズのたびに描く前に直す
+    fn clamp_detail_scroll(&mut self, area: Rect) {
+        let Some(max) = self.detail_popup().map(|popup| popup.max_scroll(area)) else {
+            return;
+        };
+        if let Some(scroll) = self.detail_scroll.as_mut() {
+            *scroll = (*scroll).min(max);
+        }
+    }
+
+    // クリックされた位置にある予定のインデックス
+    fn event_at(&self, area: Rect, column: u16, row: u16) -> Option<usize> {
+        let columns: Vec<Vec<EventView>> = self
+            .columns()
+            .into_iter()
+            .map(|events| events.into_iter().map(|(_, view)| view.clone()).collect())
+            .collect();
+        let (i, j) = ui::event_at(area, self.config.layout, &columns, column, row)?;
+        Some(columns[..i].iter().map(Vec::len).sum::<usize>() + j)
+    }
+
+    // 1分ごとの定期チェック
+    fn tick(&mut self, now: DateTime<Tz>, idle_timeout: Duration) {
+        // 日付が変わった場合や、別の日を表示したまましばらく操作がない場合は今日の予定を再取得
+        if now.date_naive() != self.fetched_time.date_naive()
+            && self.last_input.elapsed() >= idle_timeout
+        {
+            self.date_input = None;
+            self.fetch_date_events(now);
+        } else if self.fetch_error.is_some() && !self.loading {
+            // 前回の取得に失敗していたら再試行する
+            self.fetch_date_events(self.fetched_time);
+        }
+        // 別の日を表示している間もリマインダーを出せるよう今日の予定を用意する
+        self.fetch_today_events(now);
+    }
+
+    // 取得した予定を絞り込み・書き換えて表示用の情報を作り直す
+    pub fn set_events(&mut self, events: Vec<EventModel>) {
+        let events = self.prepare(events);
+        // 表示中の範囲に今日が入っていれば、リマインダーとチャイムにもこの結果を使う
+        let today = Utc::now().with_timezone(&Tokyo).date_naive();
+        if self.dates().contains(&today) {
+            self.today = Some((today, events.clone()));
+        }
+        self.events = Some(events);
+        self.build_views();
+    }
+
+    fn prepare(&self, mut events: Vec<EventModel>) -> Vec<EventModel> {
+        events.retain(|event| self.config.filter.shows(event));
+        rules::apply(&self.config.rules, events)
+    }
+
+    // 今日の分だけの取得結果。失敗したら次のTickでまた取得する
+    fn apply_today_result(&mut self, date: NaiveDate, result: Result<Vec<EventModel>, FetchError>) {
+        self.fetching_today = false;
+        if let Ok(events) = result {
+            self.today = Some((date, self.prepare(events)));
+        }
+    }
+
+    // 予定の取得結果を反映する
+    fn apply_fetch_result(&mut self, id: u64, result: Result<Vec<EventModel>, FetchError>) {
+        // 取得中に別の日へ移動していた場合は古い結果なので捨てる
+        if id != self.fetch_id {
+            return;
+        }
+        self.loading = false;
+        match result {
+            Ok(events) => {
+                self.set_events(events);
+                self.fetch_error = None;
+                // 同じ日の再取得で予定の数が減っていても選択が範囲外にならないようにする
+                let count = self.displayed_events().len();
+                if self.selected.is_some_and(|i| i >= count) {
+                    self.selected = None;
+                    self.detail_scroll = None;
+                }
+            }
+            Err(error) => self.fetch_error = Some(error.to_string()),
+        }
+    }
+}
+
+// 今日の予定。日付が変わってまだ取得していなければNone
+// Appの他のフィールドを書き換えながら使うので、フィールドだけを借りる
+fn today_events(
+    today: &Option<(NaiveDate, Vec<EventModel>)>,
+    now: DateTime<Tz>,
+) -> Option<&[EventModel]> {
+    today
+        .as_ref()
+        .filter(|(date, _)| *date == now.date_naive())
+        .map(|(_, events)| events.as_slice())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Continue,
+    Quit,
+}
+
+fn handle_key(app: &mut App, code: KeyCode) -> Action {
+    // 日付入力中はキーを入力欄に回す
+    if let Some(input) = app.date_input.as_mut() {
+        match code {
+            KeyCode::Char(c) if c.is_ascii_digit() || c == '-' => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let input = app.date_input.take().unwrap_or_default();
+                if let Ok(date) = NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+                    app.show_date(date);
+                }
+            }
+            KeyCode::Esc => app.date_input = None,
+            _ => {}
+        }
+        return Action::Continue;
+    }
+
+    // 詳細ポップアップを開いている間はスクロールと閉じる操作だけ受け付ける
+    if let Some(scroll) = app.detail_scroll.as_mut() {
+        match code {
+            KeyCode::Char('q') => return Action::Quit,
+            KeyCode::Down | KeyCode::Char('j') => *scroll = scroll.saturating_add(1),
+            KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+            KeyCode::PageDown => *scroll = scroll.saturating_add(10),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+            KeyCode::Esc | KeyCode::Enter => app.detail_scroll = None,
+            _ => {}
+        }
+        return Action::Continue;
+    }
+
+    // 複数日表示のときは表示している日数ずつ移動する
+    let showing = app.fetched_time.date_naive();
+    let step = chrono::Days::new(app.days.into());
+    match code {
+        KeyCode::Char('q') => return Action::Quit,
+        KeyCode::Left | KeyCode::Char('h') => app.show_date(showing - step),
+        KeyCode::Right | KeyCode::Char('l') => app.show_date(showing + step),
+        KeyCode::Char('w') => app.toggle_days(),
+        KeyCode::Char('t') => app.fetch_date_events(Utc::now().with_timezone(&Tokyo)),
+        KeyCode::Char('g') => app.date_input = Some(String::new()),
+        KeyCode::Down | KeyCode::Char('j') => app.select_next(),
+        KeyCode::Up | KeyCode::Char('k') => app.select_previous(),
+        KeyCode::Enter if app.selected.is_some() => app.detail_scroll = Some(0),
+        KeyCode::Esc => app.selected = None,
+        _ => {}
+    }
+    Action::Continue
+}
+
+// マウス操作を反映する。再描画が必要なときはtrueを返す
+fn handle_mouse(app: &mut App, mouse: MouseEvent, area: Rect) -> bool {
+    match (mouse.kind, app.detail_scroll.as_mut()) {
+        (MouseEventKind::ScrollDown, Some(scroll)) => *scroll = scroll.saturating_add(1),
+        (MouseEventKind::ScrollUp, Some(scroll)) => *scroll = scroll.saturating_sub(1),
+        (MouseEventKind::ScrollDown, None) => app.select_next(),
+        (MouseEventKind::ScrollUp, None) => app.select_previous(),
+        // ポップアップ表示中のクリックは閉じるだけにする
+        (MouseEventKind::Down(MouseButton::Left), Some(_)) => app.detail_scroll = None,
+        (MouseEventKind::Down(MouseButton::Left), None) => {
+            app.selected = app.event_at(area, mouse.column, mouse.row);
+            if app.selected.is_some() {
+                app.detail_scroll = Some(0);
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+// 端末の入力と1分ごとのチェックをAppEventとしてチャネルに流す
+fn spawn_event_sources(sender: UnboundedSender<AppEvent>) {
+    // crosstermの読み込みはblockingなので専用スレッドで待つ
+    let input_sender = sender.clone();
+    std::thread::spawn(move || {
+        while let Ok(event) = crossterm::event::read() {
+            if input_sender.send(AppEvent::Input(event)).is_err() {
+                break;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let check_interval = Duration::from_secs(60); // 1分ごとにチェック
+        let mut interval =
+            tokio::time::interval_at(tokio::time::Instant::now() + check_interval, check_interval);
+        loop {
+            interval.tick().await;
+            if sender.send(AppEvent::Tick).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+pub async fn run_app<B: Backend + Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    mut receiver: UnboundedReceiver<AppEvent>,
+    idle_timeout: Duration,
+    heartbeat: Option<&Path>,
+) -> Result<()> {
+    spawn_event_sources(app.sender.clone());
+    app.redraw(terminal)?;
+
+    while let Some(event) = receiver.recv().await {
+        match event {
+            AppEvent::Quit => return Ok(()),
+            AppEvent::Input(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                app.last_input = Instant::now();
+                app.reminder = None;
+                // rawモードではCtrl-Cはシグナルではなくキー入力として届く
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    return Ok(());
+                }
+                // 画面を消しているときのキーは起こすだけにする
+                if app.blank.is_blanked() {
+                    app.blank.wake(Utc::now().with_timezone(&Tokyo));
+                } else if handle_key(app, key.code) == Action::Quit {
+                    return Ok(());
+                }
+            }
+            AppEvent::Input(Event::Mouse(_)) if app.blank.is_blanked() => {
+                app.blank.wake(Utc::now().with_timezone(&Tokyo));
+            }
+            AppEvent::Input(Event::Mouse(mouse)) => {
+                app.last_input = Instant::now();
+                let area = terminal.size()?;
+                if !handle_mouse(app, mouse, Rect::new(0, 0, area.width, area.height)) {
+                    continue;
+                }
+            }
+            // 端末のサイズやズームが変わったらすぐに描き直す
+            AppEvent::Input(Event::Resize(_, _)) => {}
+            AppEvent::Input(_) => continue,
+            // 終わった予定や次の予定までの残り時間が変わるので毎分描き直す
+            AppEvent::Tick => app.tick(Utc::now().with_timezone(&Tokyo), idle_timeout),
+            AppEvent::FetchCompleted { id, events } => app.apply_fetch_result(id, Ok(events)),
+            AppEvent::FetchFailed { id, error } => app.apply_fetch_result(id, Err(error)),
+            AppEvent::TodayFetched { date, result } => app.apply_today_result(date, result),
+            // supervisorに起動し直してもらえるよう、qでの終了とは区別してエラーで返す
+            AppEvent::FetchPanicked => anyhow::bail!("fetch thread panicked"),
+        }
+        let size = terminal.size()?;
+        app.clamp_detail_scroll(Rect::new(0, 0, size.width, size.height));
+        let now = Utc::now().with_timezone(&Tokyo);
+        app.update_blank(now);
+        if app.check_reminders(now) {
+            let backend = terminal.backend_mut();
+            backend.write_all(b"\x07")?;
+            Write::flush(backend)?;
+        }
+        app.redraw(terminal)?;
+        // 毎分のTickでも描き直すので、止まっていなければ1分ごとに更新される
+        if let Some(path) = heartbeat {
+            supervisor::write_heartbeat(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike};
+    use google_calendar3::api::{Event, EventDateTime};
+
+    use super::*;
+    use crate::calendar::Calendar;
+
+    type Requests = Arc<Mutex<Vec<(NaiveDate, u32)>>>;
+
+    // 取得を頼まれた日付と日数を記録し、予定は返さない
+    struct MockFetcher {
+        requests: Requests,
+    }
+
+    impl FetchEvents for MockFetcher {
+        fn fetch_date_events(
+            &mut self,
+            date: DateTime<Tz>,
+            days: u32,
+        ) -> Result<Vec<EventModel>, FetchError> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((date.date_naive(), days));
+            Ok(Vec::new())
+        }
+    }
+
+    fn app(days: u32) -> (App, UnboundedReceiver<AppEvent>, Requests) {
+        let requests = Requests::default();
+        let fetcher = MockFetcher {
+            requests: Arc::clone(&requests),
+        };
+        let date = Tokyo.with_ymd_and_hms(2023, 10, 2, 0, 0, 0).unwrap();
+        let (app, receiver) = App::new(Config::default(), fetcher, date, days);
+        (app, receiver, requests)
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    // 別スレッドでの取得が終わるのを待って、頼まれた日付と日数を返す
+    async fn fetched(
+        receiver: &mut UnboundedReceiver<AppEvent>,
+        requests: &Requests,
+    ) -> Vec<(NaiveDate, u32)> {
+        receiver.recv().await.unwrap();
+        requests.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_step_days() {
+        let (mut app, mut receiver, requests) = app(3);
+        handle_key(&mut app, KeyCode::Char('l'));
+        assert_eq!(app.fetched_time.date_naive(), date(2023, 10, 5));
+        assert_eq!(
+            fetched(&mut receiver, &requests).await,
+            vec![(date(2023, 10, 5), 3)]
+        );
+
+        handle_key(&mut app, KeyCode::Char('h'));
+        handle_key(&mut app, KeyCode::Char('h'));
+        assert_eq!(app.fetched_time.date_naive(), date(2023, 9, 29));
+    }
+
+    #[tokio::test]
+    async fn test_date_input() {
+        let (mut app, _receiver, requests) = app(1);
+        for code in "g2023-13-01".chars().map(KeyCode::Char) {
+            handle_key(&mut app, code);
+        }
+        assert_eq!(app.date_input.as_deref(), Some("2023-13-01"));
+        // 日付として読めない入力は閉じるだけで移動しない
+        handle_key(&mut app, KeyCode::Enter);
+        assert_eq!(app.date_input, None);
+        assert_eq!(app.fetched_time.date_naive(), date(2023, 10, 2));
+        assert_eq!(app.fetch_id, 0);
+        assert!(requests.lock().unwrap().is_empty());
+
+        for code in "g2023-10-20".chars().map(KeyCode::Char) {
+            handle_key(&mut app, code);
+        }
+        handle_key(&mut app, KeyCode::Enter);
+        assert_eq!(app.fetched_time.date_naive(), date(2023, 10, 20));
+    }
+
+    #[tokio::test]
+    async fn test_escape() {
+        let (mut app, _receiver, _) = app(1);
+        handle_key(&mut app, KeyCode::Char('g'));
+        handle_key(&mut app, KeyCode::Char('2'));
+        handle_key(&mut app, KeyCode::Esc);
+        assert_eq!(app.date_input, None);
+        assert_eq!(app.fetch_id, 0);
+
+        // 日付入力中でなければ選択を外す。qは入力欄ではなく終了に使われる
+        app.selected = Some(0);
+        handle_key(&mut app, KeyCode::Esc);
+        assert_eq!(app.selected, None);
+        assert_eq!(handle_key(&mut app, KeyCode::Char('q')), Action::Quit);
+    }
+
+    #[tokio::test]
+    async fn test_detail_scroll_clamped() {
+        let (mut app, _receiver, _) = app(1);
+        let time = |hour| EventDateTime {
+            date_time: Some(app.fetched_time.with_hour(hour).unwrap().to_utc()),
+            ..Default::default()
+        };
+        let description = vec!["議事録"; 30].join("<br>");
+        app.set_events(vec![EventModel::new(
+            Event {
+                start: Some(time(10)),
+                end: Some(time(11)),
+                description: Some(description),
+                ..Default::default()
+            },
+            Calendar::Primary,
+        )]);
+        handle_key(&mut app, KeyCode::Char('j'));
+        handle_key(&mut app, KeyCode::Enter);
+        for _ in 0..100 {
+            handle_key(&mut app, KeyCode::Char('j'));
+        }
+        // 下まで行ったら1回で上へ戻り始める
+        let area = Rect::new(0, 0, 50, 20);
+        app.clamp_detail_scroll(area);
+        assert_eq!(app.detail_scroll, Some(33 - 14));
+        handle_key(&mut app, KeyCode::Char('k'));
+        assert_eq!(app.detail_scroll, Some(33 - 15));
+    }
+
+    #[tokio::test]
+    async fn test_idle_return() {
+        let (mut app, mut receiver, requests) = app(1);
+        let now = Utc::now().with_timezone(&Tokyo);
+        // 操作してから時間が経っていなければ表示中の日のままにする
+        app.tick(now, Duration::from_secs(60 * 60));
+        assert_eq!(app.fetched_time.date_naive(), date(2023, 10, 2));
+        // リマインダー用に今日の分だけは取得しておく
+        assert_eq!(
+            fetched(&mut receiver, &requests).await,
+            vec![(now.date_naive(), 1)]
+        );
+
+        app.tick(now, Duration::ZERO);
+        assert_eq!(app.fetched_time.date_naive(), now.date_naive());
+    }
+
+    // 最初の取得でだけpanicする
+    struct PanickingFetcher {
+        calls: u32,
+    }
+
+    impl FetchEvents for PanickingFetcher {
+        fn fetch_date_events(
+            &mut self,
+            _date: DateTime<Tz>,
+            _days: u32,
+        ) -> Result<Vec<EventModel>, FetchError> {
+            self.calls += 1;
+            if self.calls == 1 {
+                panic!("fetch failed unexpectedly");
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_panic() {
+        let date = Tokyo.with_ymd_and_hms(2023, 10, 2, 0, 0, 0).unwrap();
+        let fetcher = PanickingFetcher { calls: 0 };
+        let (mut app, mut receiver) = App::new(Config::default(), fetcher, date, 1);
+        app.fetch_date_events(date);
+        assert!(matches!(
+            receiver.recv().await,
+            Some(AppEvent::FetchPanicked)
+        ));
+        // panicでロックが壊れても次の取得はできる
+        app.fetch_date_events(date);
+        assert!(matches!(
+            receiver.recv().await,
+            Some(AppEvent::FetchCompleted { id: 2, .. })
+        ));
+    }
+}
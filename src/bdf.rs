@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+// BDF形式のビットマップフォント。端末を使わずに描画するときに使う
+// 日本語も出すなら GNU Unifont (unifont.bdf) などの全角グリフを含むフォントを指定する
+#[derive(Debug)]
+pub struct BdfFont {
+    // 半角1文字分のセルの大きさ (ピクセル)
+    pub cell_width: u32,
+    pub cell_height: u32,
+    // セルの上端からベースラインまで
+    ascent: i32,
+    glyphs: HashMap<char, Glyph>,
+}
+
+#[derive(Debug)]
+struct Glyph {
+    // BBX: ビットマップの大きさと、ベースライン上の原点からのずれ
+    width: u32,
+    height: u32,
+    x_offset: i32,
+    y_offset: i32,
+    // 1行ごとのビット列。左端が先頭バイトの最上位ビット
+    rows: Vec<Vec<u8>>,
+}
+
+impl Glyph {
+    fn pixel(&self, x: u32, y: u32) -> bool {
+        self.rows
+            .get(y as usize)
+            .and_then(|row| row.get((x / 8) as usize))
+            .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    }
+}
+
+impl BdfFont {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read font: {}", path.display()))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut bounding_box = None;
+        let mut glyphs = HashMap::new();
+        let mut widths = HashMap::new();
+
+        let mut lines = text.lines();
+        while let Some(line) = lines.next() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => bounding_box = Some(Self::numbers(words, 4)?),
+                Some("STARTCHAR") => {
+                    let mut encoding = None;
+                    let mut width = None;
+                    let mut bbx = None;
+                    for line in lines.by_ref() {
+                        let mut words = line.split_whitespace();
+                        match words.next() {
+                            Some("ENCODING") => encoding = Some(Self::numbers(words, 1)?[0]),
+                            Some("DWIDTH") => width = Some(Self::numbers(words, 1)?[0]),
+                            Some("BBX") => bbx = Some(Self::numbers(words, 4)?),
+                            Some("BITMAP") => break,
+                            _ => {}
+                        }
+                    }
+                    let bbx = bbx.ok_or_else(|| anyhow!("BBX is missing"))?;
+                    let rows = lines
+                        .by_ref()
+                        .take_while(|line| line.trim() != "ENDCHAR")
+                        .map(Self::hex_row)
+                        .collect::<Result<Vec<_>>>()?;
+                    // ENCODING -1 はUnicodeに対応しないグリフ
+                    let Some(c) = encoding
+                        .and_then(|encoding| u32::try_from(encoding).ok())
+                        .and_then(char::from_u32)
+                    else {
+                        continue;
+                    };
+                    widths.insert(c, width.unwrap_or(bbx[0]));
+                    glyphs.insert(
+                        c,
+                        Glyph {
+                            width: bbx[0].max(0) as u32,
+                            height: bbx[1].max(0) as u32,
+                            x_offset: bbx[2],
+                            y_offset: bbx[3],
+                            rows,
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let bounding_box = bounding_box.ok_or_else(|| anyhow!("FONTBOUNDINGBOX is missing"))?;
+        // 全角グリフを含むフォントはFONTBOUNDINGBOXが全角の幅なので、半角の幅は英字から取る
+        let cell_width = widths.get(&'M').copied().unwrap_or(bounding_box[0]).max(1) as u32;
+        Ok(BdfFont {
+            cell_width,
+            cell_height: bounding_box[1].max(1) as u32,
+            ascent: bounding_box[1] + bounding_box[3],
+            glyphs,
+        })
+    }
+
+    fn numbers<'a>(words: impl Iterator<Item = &'a str>, count: usize) -> Result<Vec<i32>> {
+        let numbers = words
+            .take(count)
+            .map(|word| word.parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if numbers.len() != count {
+            return Err(anyhow!("expected {} numbers", count));
+        }
+        Ok(numbers)
+    }
+
+    fn hex_row(line: &str) -> Result<Vec<u8>> {
+        let line = line.trim();
+        (0..line.len())
+            .step_by(2)
+            .map(|i| {
+                let byte = line.get(i..(i + 2).min(line.len())).unwrap_or_default();
+                Ok(u8::from_str_radix(byte, 16)?)
+            })
+            .collect()
+    }
+
+    // セルの左上を(x, y)として、文字の立っているピクセルの位置を返す
+    // フォントに無い文字はセルの大きさの枠 (いわゆる豆腐) にする
+    pub fn pixels(&self, c: char, cells: u32) -> Vec<(i32, i32)> {
+        let Some(glyph) = self.glyphs.get(&c) else {
+            let (width, height) = ((self.cell_width * cells) as i32, self.cell_height as i32);
+            return (0..width)
+                .flat_map(|x| [(x, 1), (x, height - 2)])
+                .chain((1..height - 1).flat_map(|y| [(1, y), (width - 2, y)]))
+                .collect();
+        };
+        let top = self.ascent - glyph.y_offset - glyph.height as i32;
+        (0..glyph.height)
+            .flat_map(|y| (0..glyph.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| glyph.pixel(x, y))
+            .map(|(x, y)| (glyph.x_offset + x as i32, top + y as i32))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4x6 の小さなフォント
+    const FONT: &str = "STARTFONT 2.1
+FONTBOUNDINGBOX 4 6 0 -1
+CHARS 1
+STARTCHAR A
+ENCODING 65
+DWIDTH 4 0
+BBX 3 5 0 0
+BITMAP
+40
+A0
+E0
+A0
+A0
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn test_parse_and_rasterize() {
+        let font = BdfFont::parse(FONT).unwrap();
+        assert_eq!((font.cell_width, font.cell_height), (4, 6));
+
+        let pixels = font.pixels('A', 1);
+        // 上端の1ピクセルとベースライン直上の横棒
+        assert!(pixels.contains(&(1, 0)));
+        assert!(pixels.contains(&(0, 2)) && pixels.contains(&(1, 2)) && pixels.contains(&(2, 2)));
+        assert!(!pixels.contains(&(1, 3)));
+        assert_eq!(pixels.len(), 10);
+    }
+}
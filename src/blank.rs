@@ -0,0 +1,173 @@
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+
+use crate::config::BlankConfig;
+use crate::event::{EventModel, EventTime};
+use crate::start_of_day;
+
+// 画面を消しているかと、キーで起こした時刻
+#[derive(Debug, Default)]
+pub struct Blank {
+    blanked: bool,
+    woken_at: Option<DateTime<Tz>>,
+}
+
+impl Blank {
+    pub fn is_blanked(&self) -> bool {
+        self.blanked
+    }
+
+    // キーで起こした時刻から wake_minutes 分は消さない
+    pub fn wake(&mut self, now: DateTime<Tz>) {
+        self.woken_at = Some(now);
+    }
+
+    // 消す・戻すが切り替わったときに、設定されていれば実行するコマンドを返す
+    pub fn update<'a>(
+        &mut self,
+        config: &'a BlankConfig,
+        events: Option<&[EventModel]>,
+        now: DateTime<Tz>,
+    ) -> Option<&'a str> {
+        let blank = should_blank(config, events, now, self.woken_at);
+        if blank == self.blanked {
+            return None;
+        }
+        self.blanked = blank;
+        if blank {
+            config.off_command.as_deref()
+        } else {
+            config.on_command.as_deref()
+        }
+    }
+}
+
+// eventsは今日の予定。まだ取得していなければNoneで、予定が終わったかどうかは判断しない
+pub fn should_blank(
+    config: &BlankConfig,
+    events: Option<&[EventModel]>,
+    now: DateTime<Tz>,
+    woken_at: Option<DateTime<Tz>>,
+) -> bool {
+    let wake = Duration::minutes(config.wake_minutes as i64);
+    let awake = woken_at.is_some_and(|at| now - at < wake);
+    if !config.enabled || awake {
+        return false;
+    }
+    let scheduled = config
+        .schedule
+        .as_ref()
+        .is_some_and(|schedule| schedule.contains(now.time()));
+    scheduled
+        || (config.after_last_event && events.is_some_and(|events| no_more_events(events, now)))
+}
+
+// 今日の時刻指定の予定がすべて終わったか
+fn no_more_events(events: &[EventModel], now: DateTime<Tz>) -> bool {
+    let tomorrow = start_of_day(now.date_naive() + chrono::Days::new(1));
+    !events
+        .iter()
+        .any(|event| match (event.start(), event.end()) {
+            (Some(EventTime::DateTime(start)), Some(EventTime::DateTime(end))) => {
+                start < tomorrow && end > now
+            }
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+    use google_calendar3::api::{Event, EventDateTime};
+
+    use super::*;
+    use crate::calendar::Calendar;
+    use crate::config::TimeRange;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Tz> {
+        Tokyo
+            .with_ymd_and_hms(2023, 10, 2, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn event(start: u32, end: u32) -> EventModel {
+        let time = |hour| EventDateTime {
+            date_time: Some(at(hour, 0).to_utc()),
+            ..Default::default()
+        };
+        EventModel::new(
+            Event {
+                start: Some(time(start)),
+                end: Some(time(end)),
+                ..Default::default()
+            },
+            Calendar::Primary,
+        )
+    }
+
+    fn config() -> BlankConfig {
+        BlankConfig {
+            enabled: true,
+            schedule: Some(TimeRange {
+                from: at(23, 0).time(),
+                until: at(7, 0).time(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let config = config();
+        assert!(should_blank(&config, None, at(23, 30), None));
+        assert!(should_blank(&config, None, at(6, 59), None));
+        assert!(!should_blank(&config, None, at(7, 0), None));
+        let disabled = BlankConfig {
+            enabled: false,
+            ..config
+        };
+        assert!(!should_blank(&disabled, None, at(23, 30), None));
+    }
+
+    #[test]
+    fn test_after_last_event() {
+        let config = BlankConfig {
+            after_last_event: true,
+            ..config()
+        };
+        let events = [event(9, 10), event(13, 15)];
+        assert!(!should_blank(&config, Some(&events), at(14, 0), None));
+        assert!(should_blank(&config, Some(&events), at(15, 0), None));
+        // 取得前は予定が無いとはみなさない
+        assert!(!should_blank(&config, None, at(15, 0), None));
+        assert!(should_blank(&config, Some(&[]), at(15, 0), None));
+    }
+
+    #[test]
+    fn test_wake() {
+        let config = config();
+        let woken_at = Some(at(23, 30));
+        assert!(!should_blank(&config, None, at(23, 34), woken_at));
+        assert!(should_blank(&config, None, at(23, 35), woken_at));
+    }
+
+    #[test]
+    fn test_commands() {
+        let config = BlankConfig {
+            off_command: Some("off".to_string()),
+            on_command: Some("on".to_string()),
+            ..config()
+        };
+        let mut blank = Blank::default();
+        assert_eq!(blank.update(&config, None, at(22, 59)), None);
+        assert_eq!(blank.update(&config, None, at(23, 0)), Some("off"));
+        assert!(blank.is_blanked());
+        // 消したままなら何度呼んでも実行しない
+        assert_eq!(blank.update(&config, None, at(23, 1)), None);
+        blank.wake(at(23, 2));
+        assert_eq!(blank.update(&config, None, at(23, 2)), Some("on"));
+        assert!(!blank.is_blanked());
+        assert_eq!(blank.update(&config, None, at(23, 7)), Some("off"));
+    }
+}
@@ -18,6 +18,14 @@ impl Calendar {
         }
     }
 
+    pub fn name(&self) -> &'static str {
+        match self {
+            Calendar::Primary => "Primary",
+            Calendar::Private => "Private",
+            Calendar::University => "University",
+        }
+    }
+
     pub fn color(&self) -> Color {
         match self {
             Calendar::Primary => Color::Red,
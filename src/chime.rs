@@ -0,0 +1,137 @@
+use std::process::Command;
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+use crate::config::ChimeConfig;
+use crate::event::EventModel;
+use crate::process;
+use crate::reminder::Scheduler;
+
+// 予定の開始前と開始時にチャイムを鳴らす。鳴らす時刻の判定はリマインダーと同じScheduler
+#[derive(Debug, Default)]
+pub struct Chime {
+    scheduler: Scheduler,
+}
+
+impl Chime {
+    pub fn check(&mut self, config: &ChimeConfig, events: &[EventModel], now: DateTime<Tz>) {
+        // 同時に複数の予定が該当しても1回だけ鳴らす
+        if !due_chimes(config, events, now, &mut self.scheduler).is_empty() {
+            process::spawn_detached(Command::new(&config.player).arg(&config.sound));
+        }
+    }
+}
+
+// nowの時点でチャイムを鳴らす予定。通知済みのものはfiredに記録して二度は返さない
+pub fn due_chimes<'a>(
+    config: &ChimeConfig,
+    events: &'a [EventModel],
+    now: DateTime<Tz>,
+    fired: &mut Scheduler,
+) -> Vec<&'a EventModel> {
+    if !config.enabled {
+        return Vec::new();
+    }
+    let lead_minutes: Vec<i64> = config
+        .lead_minutes
+        .into_iter()
+        .chain(config.at_start.then_some(0))
+        .collect();
+    // 静かにする時間帯の分も通知済みにしておき、明けてからまとめて鳴らないようにする
+    let due = fired.due(events, |_| lead_minutes.clone(), now);
+    let quiet = config
+        .quiet_hours
+        .as_ref()
+        .is_some_and(|quiet| quiet.contains(now.time()));
+    if quiet {
+        return Vec::new();
+    }
+    due
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+    use google_calendar3::api::{Event, EventDateTime};
+
+    use super::*;
+    use crate::calendar::Calendar;
+    use crate::config::TimeRange;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Tz> {
+        Tokyo
+            .with_ymd_and_hms(2023, 10, 2, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn event(id: &str, hour: u32) -> EventModel {
+        EventModel::new(
+            Event {
+                id: Some(id.to_string()),
+                start: Some(EventDateTime {
+                    date_time: Some(at(hour, 0).to_utc()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            Calendar::Primary,
+        )
+    }
+
+    fn config(quiet_hours: Option<(DateTime<Tz>, DateTime<Tz>)>) -> ChimeConfig {
+        ChimeConfig {
+            enabled: true,
+            lead_minutes: Some(5),
+            quiet_hours: quiet_hours.map(|(from, until)| TimeRange {
+                from: from.time(),
+                until: until.time(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lead_and_start_fire_once() {
+        let config = config(None);
+        let events = [event("a", 10)];
+        let mut fired = Scheduler::default();
+        assert!(due_chimes(&config, &events, at(9, 54), &mut fired).is_empty());
+        assert_eq!(due_chimes(&config, &events, at(9, 55), &mut fired).len(), 1);
+        // 毎分のチェックや再取得で同じ予定がまた来ても鳴らし直さない
+        assert!(due_chimes(&config, &events, at(9, 55), &mut fired).is_empty());
+        assert!(due_chimes(&config, &events, at(9, 56), &mut fired).is_empty());
+        assert_eq!(due_chimes(&config, &events, at(10, 0), &mut fired).len(), 1);
+        assert!(due_chimes(&config, &events, at(10, 1), &mut fired).is_empty());
+
+        let disabled = ChimeConfig {
+            enabled: false,
+            ..config
+        };
+        let mut fired = Scheduler::default();
+        assert!(due_chimes(&disabled, &events, at(10, 0), &mut fired).is_empty());
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let config = config(Some((at(11, 0), at(11, 57))));
+        let events = [event("a", 12)];
+        let mut fired = Scheduler::default();
+        assert!(due_chimes(&config, &events, at(11, 55), &mut fired).is_empty());
+        // 5分前のチャイムはまだ受け付ける時間だが、静かにする間に来た分は明けてから鳴らさない
+        assert!(due_chimes(&config, &events, at(11, 57), &mut fired).is_empty());
+        assert_eq!(due_chimes(&config, &events, at(12, 0), &mut fired).len(), 1);
+    }
+
+    #[test]
+    fn test_overnight_quiet_hours() {
+        let config = config(Some((at(22, 0), at(7, 0))));
+        let events = [event("late", 23), event("early", 6), event("morning", 8)];
+        let mut fired = Scheduler::default();
+        assert!(due_chimes(&config, &events, at(23, 0), &mut fired).is_empty());
+        assert!(due_chimes(&config, &events, at(6, 0), &mut fired).is_empty());
+        assert!(due_chimes(&config, &events, at(7, 1), &mut fired).is_empty());
+        assert_eq!(due_chimes(&config, &events, at(7, 55), &mut fired).len(), 1);
+    }
+}
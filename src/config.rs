@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::NaiveTime;
+use ratatui::style::Color;
+use serde::{Deserialize, Deserializer};
+
+use crate::event::EventModel;
+use crate::rules::Rule;
+
+// config.toml の内容。ファイルが無い場合や項目が省略された場合は既定値を使う
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub layout: LayoutMode,
+    pub theme: ThemeConfig,
+    pub event_box: EventBoxConfig,
+    pub privacy: PrivacyConfig,
+    pub filter: FilterConfig,
+    pub rules: Vec<Rule>,
+    pub reminders: ReminderConfig,
+    pub chime: ChimeConfig,
+    pub blank: BlankConfig,
+    pub pixel: PixelConfig,
+    pub epaper: EpaperConfig,
+    pub dashboard: DashboardConfig,
+}
+
+// 時間軸を縦に流すか横に流すか
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutMode {
+    // 端末の縦横比から決める
+    #[default]
+    Auto,
+    Vertical,
+    Horizontal,
+}
+
+// [theme] の設定。色は "red" などの名前、"#rrggbb"、256色の番号 ("208") で書ける
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub name: ThemeName,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub background: Option<Color>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub foreground: Option<Color>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub now_marker: Option<Color>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub grid: Option<Color>,
+    // 予定ごとの色 (colorId) を使うか。省略時はmono以外のテーマで使う
+    pub use_event_colors: Option<bool>,
+    // カレンダー名 (Primary, Private, University) ごとの予定の背景色
+    #[serde(deserialize_with = "deserialize_color_map")]
+    pub calendars: HashMap<String, Color>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    // e-inkなど白黒しか出ない画面向け
+    Mono,
+}
+
+// [event_box] の設定。予定の枠に高さの余裕があるとき、タイトルの下に出す項目を選ぶ
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EventBoxConfig {
+    pub location: bool,
+    // 参加者として予約された会議室
+    pub room: bool,
+    // Meetなどのビデオ会議のURL
+    pub conference_link: bool,
+}
+
+impl Default for EventBoxConfig {
+    fn default() -> Self {
+        EventBoxConfig {
+            location: true,
+            room: true,
+            conference_link: true,
+        }
+    }
+}
+
+impl EventBoxConfig {
+    pub fn details(&self, event: &EventModel) -> Vec<String> {
+        let mut details = Vec::new();
+        if let Some(location) = event.location().filter(|_| self.location) {
+            details.push(location.to_string());
+        }
+        // 会議室の予約は場所にも同じ名前が入ることが多いので、重ならないときだけ出す
+        if let Some(room) = event.room().filter(|_| self.room) {
+            if !details.iter().any(|detail| detail.contains(room)) {
+                details.push(room.to_string());
+            }
+        }
+        if let Some(link) = event.conference_link().filter(|_| self.conference_link) {
+            // 枠は狭いのでスキームは省く
+            details.push(link.trim_start_matches("https://").to_string());
+        }
+        details
+    }
+}
+
+// [privacy] の設定。人目に付く場所に置くとき、非公開の予定の中身を隠す
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    pub mask_private: bool,
+    // 隠した予定の代わりに出すタイトル
+    pub mask_title: String,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig {
+            mask_private: false,
+            mask_title: "(private)".to_string(),
+        }
+    }
+}
+
+impl PrivacyConfig {
+    pub fn masks(&self, event: &EventModel) -> bool {
+        self.mask_private && event.is_private()
+    }
+
+    pub fn title(&self, event: &EventModel) -> String {
+        if self.masks(event) {
+            self.mask_title.clone()
+        } else {
+            event.title()
+        }
+    }
+}
+
+// [filter] の設定。[filter.calendars.<カレンダー名>] でカレンダーごとに上書きできる
+// キャンセルされた予定は常に出さない
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    // 自分が「不参加」と返事した予定を隠す
+    pub hide_declined: bool,
+    // 「空き時間」として登録された予定を隠す
+    pub hide_free: bool,
+    // 出欠が「未定」の予定を隠す。隠さない場合は斜線付きで出す
+    pub hide_tentative: bool,
+    pub calendars: HashMap<String, CalendarFilter>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CalendarFilter {
+    pub hide_declined: Option<bool>,
+    pub hide_free: Option<bool>,
+    pub hide_tentative: Option<bool>,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            hide_declined: true,
+            hide_free: false,
+            hide_tentative: false,
+            calendars: HashMap::new(),
+        }
+    }
+}
+
+impl FilterConfig {
+    pub fn shows(&self, event: &EventModel) -> bool {
+        if event.is_cancelled() {
+            return false;
+        }
+        let calendar = self.calendars.get(event.calendar().name());
+        let rule = |global: bool, select: fn(&CalendarFilter) -> Option<bool>| {
+            calendar.and_then(select).unwrap_or(global)
+        };
+        let hidden_by_response = match event.response_status() {
+            Some("declined") => rule(self.hide_declined, |c| c.hide_declined),
+            Some("tentative") => rule(self.hide_tentative, |c| c.hide_tentative),
+            _ => false,
+        };
+        let hidden_as_free = event.is_free() && rule(self.hide_free, |c| c.hide_free);
+        !(hidden_by_response || hidden_as_free)
+    }
+}
+
+// [reminders] の設定。予定の開始前にヘッダーへ表示し、ベルを鳴らす
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ReminderConfig {
+    pub enabled: bool,
+    // 予定に通知の設定が無い (カレンダーの既定を使う) ときに何分前に知らせるか
+    pub default_minutes: i64,
+    pub bell: bool,
+    // 知らせるときに sh -c で実行するコマンド。EVENT_TITLE, EVENT_START, EVENT_LOCATION を渡す
+    pub command: Option<String>,
+}
+
+impl Default for ReminderConfig {
+    fn default() -> Self {
+        ReminderConfig {
+            enabled: true,
+            default_minutes: 10,
+            bell: true,
+            command: None,
+        }
+    }
+}
+
+// [chime] の設定。スピーカーを繋いだ壁掛け表示で、予定の開始前と開始時に音を鳴らす
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ChimeConfig {
+    pub enabled: bool,
+    // 鳴らすWAVファイル
+    pub sound: PathBuf,
+    // WAVファイルを引数に渡して実行するプレイヤー
+    pub player: String,
+    // 開始の何分前にも鳴らすか。開始時だけでよければ省略する
+    pub lead_minutes: Option<i64>,
+    pub at_start: bool,
+    pub quiet_hours: Option<TimeRange>,
+}
+
+impl Default for ChimeConfig {
+    fn default() -> Self {
+        ChimeConfig {
+            enabled: false,
+            sound: PathBuf::from("chime.wav"),
+            player: "aplay".to_string(),
+            lead_minutes: None,
+            at_start: true,
+            quiet_hours: None,
+        }
+    }
+}
+
+// [blank] の設定。夜間などに画面を消す。キーを押すとwake_minutes分だけ表示を戻す
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BlankConfig {
+    pub enabled: bool,
+    pub schedule: Option<TimeRange>,
+    // 今日の予定が全部終わったら、その日の終わりまで消す。朝に戻すのはscheduleに任せる
+    pub after_last_event: bool,
+    // 消すとき、戻すときに sh -c で実行するコマンド。無ければ黒く塗るだけ
+    pub off_command: Option<String>,
+    pub on_command: Option<String>,
+    pub wake_minutes: u64,
+}
+
+impl Default for BlankConfig {
+    fn default() -> Self {
+        BlankConfig {
+            enabled: false,
+            schedule: None,
+            after_last_event: false,
+            off_command: None,
+            on_command: None,
+            wake_minutes: 5,
+        }
+    }
+}
+
+// [pixel] の設定。--framebuffer や --png で端末を使わずに描くときのフォントと大きさ
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PixelConfig {
+    // BDF形式のビットマップフォント
+    pub font: PathBuf,
+    // --png で書き出す画像の大きさ。フレームバッファは画面の大きさを使う
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for PixelConfig {
+    fn default() -> Self {
+        PixelConfig {
+            font: PathBuf::from("font.bdf"),
+            width: 800,
+            height: 480,
+        }
+    }
+}
+
+// [epaper] の設定。--epaper でSPI接続の電子ペーパー (Waveshareの7.5インチなど) に描くときの配線と画面
+// フォントは [pixel] のものを使う
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EpaperConfig {
+    // 制御用のGPIOの番号 (BCM)。既定はWaveshareのHATの配線
+    pub dc_pin: u32,
+    pub reset_pin: u32,
+    pub busy_pin: u32,
+    pub width: u32,
+    pub height: u32,
+    pub colors: EpaperColors,
+    // 部分更新をこの回数続けたら全体を書き換えて残像を消す
+    pub full_refresh_every: u32,
+    // 次の予定までの残り時間をこの分単位で切り上げて出す。毎分の部分更新でパネルを傷めないため
+    pub countdown_minutes: i64,
+}
+
+impl Default for EpaperConfig {
+    fn default() -> Self {
+        EpaperConfig {
+            dc_pin: 25,
+            reset_pin: 17,
+            busy_pin: 24,
+            width: 800,
+            height: 480,
+            colors: EpaperColors::default(),
+            full_refresh_every: 20,
+            countdown_minutes: 5,
+        }
+    }
+}
+
+// 電子ペーパーの色数。3色のパネルは部分更新ができないので毎回全体を書き換える
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EpaperColors {
+    #[default]
+    BlackWhite,
+    BlackWhiteRed,
+}
+
+// [dashboard] の設定。同じ表示をブラウザから見られるようにする
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DashboardConfig {
+    // 待ち受けるアドレス ("0.0.0.0:8080" など)。無ければHTTPサーバーを起動しない
+    pub listen: Option<String>,
+}
+
+// 1日のうちの時間帯。from > until なら日付をまたぐ (22:00~07:00 など)
+#[derive(Debug, Deserialize)]
+pub struct TimeRange {
+    #[serde(deserialize_with = "deserialize_required_time")]
+    pub from: NaiveTime,
+    #[serde(deserialize_with = "deserialize_required_time")]
+    pub until: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.from <= self.until {
+            self.from <= time && time < self.until
+        } else {
+            self.from <= time || time < self.until
+        }
+    }
+}
+
+fn parse_time<E: serde::de::Error>(value: &str) -> Result<NaiveTime, E> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| E::custom(format!("invalid time: {:?}", value)))
+}
+
+// "HH:MM" 形式の時刻
+pub fn deserialize_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveTime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_time(&value))
+        .transpose()
+}
+
+fn deserialize_required_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<NaiveTime, D::Error> {
+    parse_time(&String::deserialize(deserializer)?)
+}
+
+fn parse_color<E: serde::de::Error>(value: &str) -> Result<Color, E> {
+    Color::from_str(value).map_err(|_| E::custom(format!("invalid color: {:?}", value)))
+}
+
+pub fn deserialize_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_color(&value))
+        .transpose()
+}
+
+fn deserialize_color_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, Color>, D::Error> {
+    HashMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, value)| Ok((name, parse_color(&value)?)))
+        .collect()
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use google_calendar3::api::{Event, EventAttendee};
+
+    use super::*;
+    use crate::calendar::Calendar;
+
+    fn event(calendar: Calendar, status: &str, transparency: Option<&str>) -> EventModel {
+        EventModel::new(
+            Event {
+                attendees: Some(vec![EventAttendee {
+                    self_: Some(true),
+                    response_status: Some(status.to_string()),
+                    ..Default::default()
+                }]),
+                transparency: transparency.map(str::to_string),
+                ..Default::default()
+            },
+            calendar,
+        )
+    }
+
+    #[test]
+    fn test_time_range_across_midnight() {
+        let quiet: TimeRange = toml::from_str(
+            r#"
+            from = "22:00"
+            until = "07:00"
+            "#,
+        )
+        .unwrap();
+        let time = |h| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+        assert!(quiet.contains(time(23)));
+        assert!(quiet.contains(time(6)));
+        assert!(!quiet.contains(time(7)));
+        assert!(!quiet.contains(time(12)));
+    }
+
+    #[test]
+    fn test_filter_per_calendar() {
+        let filter: FilterConfig = toml::from_str(
+            r#"
+            hide_free = true
+            [calendars.University]
+            hide_declined = false
+            hide_free = false
+            "#,
+        )
+        .unwrap();
+
+        assert!(filter.shows(&event(Calendar::Primary, "accepted", None)));
+        assert!(filter.shows(&event(Calendar::Primary, "tentative", None)));
+        assert!(!filter.shows(&event(Calendar::Primary, "declined", None)));
+        assert!(!filter.shows(&event(Calendar::Primary, "accepted", Some("transparent"))));
+        assert!(filter.shows(&event(Calendar::University, "declined", None)));
+        assert!(filter.shows(&event(
+            Calendar::University,
+            "accepted",
+            Some("transparent")
+        )));
+    }
+}
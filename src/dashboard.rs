@@ -0,0 +1,373 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::Tz;
+use ratatui::style::Color;
+use serde::Serialize;
+
+use crate::event::{EventPhase, EventView};
+use crate::metrics::Metrics;
+use crate::svg::{escape, hex};
+use crate::theme::{self, Theme};
+use crate::ui::DayColumn;
+
+// 画面に出している内容の写し。描画のたびにAppが書き換え、HTTPサーバーが読む
+// 予定はAppが取得済みのものだけを使うので、ブラウザから見てもGoogleのAPIは呼ばない
+#[derive(Clone, Default)]
+pub struct Dashboard {
+    state: Arc<Mutex<Option<State>>>,
+    metrics: Metrics,
+}
+
+struct State {
+    theme: Theme,
+    header: String,
+    now: DateTime<Tz>,
+    columns: Vec<DayColumn>,
+}
+
+#[derive(Debug, Serialize)]
+struct DayJson {
+    date: NaiveDate,
+    events: Vec<EventJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct EventJson {
+    title: String,
+    start: String,
+    end: String,
+    details: Vec<String>,
+    tentative: bool,
+    color: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DashboardJson {
+    header: String,
+    updated_at: String,
+    days: Vec<DayJson>,
+}
+
+// 色はテーマから :root の変数に入れる
+const STYLE: &str = "\
+body { margin: 0; height: 100vh; display: flex; flex-direction: column; font-family: monospace;
+  background: var(--background); color: var(--foreground); }
+header { padding: 2px 8px; white-space: pre; }
+main { flex: 1; display: flex; }
+.day { flex: 1; position: relative; border-left: 1px solid var(--grid); }
+.hour { position: absolute; left: 0; right: 0; font-size: small;
+  border-top: 1px solid var(--grid); color: var(--grid); }
+.event { position: absolute; left: 2.5em; right: 2px; overflow: hidden; padding: 0 4px;
+  box-sizing: border-box; border-top: 1px solid var(--background); }
+.event.past { opacity: 0.5; }
+.event.current { border-left: 4px solid var(--foreground); font-weight: bold; }
+.event.tentative { background-image:
+  repeating-linear-gradient(45deg, transparent 0 6px, rgba(127, 127, 127, 0.4) 6px 8px); }
+.now { position: absolute; left: 0; right: 0; border-top: 2px solid var(--now); }
+";
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: String) -> Self {
+        Response {
+            status: "200 OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", status),
+        }
+    }
+}
+
+impl Dashboard {
+    // /metrics で予定の取得の状況も返す
+    pub fn new(metrics: Metrics) -> Self {
+        Dashboard {
+            state: Arc::default(),
+            metrics,
+        }
+    }
+
+    pub fn publish(&self, theme: &Theme, header: String, now: DateTime<Tz>, columns: &[DayColumn]) {
+        let state = State {
+            theme: theme.clone(),
+            header,
+            now,
+            columns: columns.to_vec(),
+        };
+        *self.state.lock().unwrap() = Some(state);
+    }
+
+    // 別スレッドで待ち受ける。アドレスが使えないなどの失敗は画面を切り替える前に返す
+    // ポートに0を指定したときのために、実際に待ち受けているアドレスを返す
+    pub fn serve(&self, address: &str) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("failed to listen on {}", address))?;
+        let local = listener.local_addr()?;
+        let dashboard = self.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // 遅いクライアントが読み込みのタイムアウトまで他の接続を待たせないよう、接続ごとにスレッドを分ける
+                let dashboard = dashboard.clone();
+                std::thread::spawn(move || dashboard.handle(stream));
+            }
+        });
+        Ok(local)
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // ヘッダーは使わないが、読み切ってから返さないとクライアントによっては接続がリセットされる
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+
+        let mut words = request_line.split_whitespace();
+        let response = match (words.next(), words.next()) {
+            (Some("GET"), Some(target)) => self.respond(target),
+            _ => Response::error("405 Method Not Allowed"),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+            response.status,
+            response.content_type,
+            response.body.len()
+        )?;
+        stream.write_all(response.body.as_bytes())
+    }
+
+    fn respond(&self, target: &str) -> Response {
+        let path = target.split('?').next().unwrap_or_default();
+        let state = self.state.lock().unwrap();
+        match (path, state.as_ref()) {
+            // 起動直後、最初の描画が終わるまで
+            ("/" | "/events.json", None) => Response::error("503 Service Unavailable"),
+            ("/", Some(state)) => Response::ok("text/html; charset=utf-8", state.html()),
+            ("/events.json", Some(state)) => match serde_json::to_string_pretty(&state.json()) {
+                Ok(json) => Response::ok("application/json", json + "\n"),
+                Err(_) => Response::error("500 Internal Server Error"),
+            },
+            // 描画前でも取得の状況は返す
+            ("/metrics", state) => {
+                let events = state.map_or(0, |state| {
+                    state.columns.iter().map(|column| column.events.len()).sum()
+                });
+                Response::ok(
+                    "text/plain; version=0.0.4; charset=utf-8",
+                    self.metrics.render(events),
+                )
+            }
+            _ => Response::error("404 Not Found"),
+        }
+    }
+}
+
+impl State {
+    fn json(&self) -> DashboardJson {
+        DashboardJson {
+            header: self.header.clone(),
+            updated_at: self.now.to_rfc3339(),
+            days: self
+                .columns
+                .iter()
+                .map(|column| DayJson {
+                    date: column.date,
+                    events: column
+                        .events
+                        .iter()
+                        .map(|event| EventJson {
+                            title: event.title.clone(),
+                            start: event.start_time.to_rfc3339(),
+                            end: event.end_time.to_rfc3339(),
+                            details: event.details.clone(),
+                            tentative: event.tentative,
+                            color: css_color(event.color),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    // TUIの縦向きの表示と同じく、日ごとの列に24時間の目盛りを引いて予定の枠を置く
+    // 1分ごとに読み込み直して表示を追いかける
+    fn html(&self) -> String {
+        let theme = &self.theme;
+        let color = |color: Color| css_color(color).unwrap_or_else(|| "inherit".to_string());
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html lang=\"ja\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta http-equiv=\"refresh\" content=\"60\">\n<title>{title}</title>\n\
+             <style>\n:root {{ --background: {}; --foreground: {}; --grid: {}; --now: {}; }}\n\
+             {STYLE}</style>\n</head>\n<body>\n<header>{title}</header>\n<main>\n",
+            color(theme.background),
+            color(theme.foreground),
+            color(theme.grid),
+            color(theme.now_marker),
+            title = escape(&self.header),
+        );
+        let percent = |slots: u16| slots as f64 * 100.0 / 48.0;
+        for column in &self.columns {
+            html.push_str("<div class=\"day\">\n");
+            for hour in 0..24 {
+                html.push_str(&format!(
+                    "<div class=\"hour\" style=\"top: {:.3}%\">{:02}</div>\n",
+                    percent(hour * 2),
+                    hour
+                ));
+            }
+            for event in &column.events {
+                html.push_str(&self.event_html(event, percent(event.start), percent(event.height)));
+            }
+            if let Some(now) = column.now {
+                let minutes = now.time().signed_duration_since(chrono::NaiveTime::MIN);
+                html.push_str(&format!(
+                    "<div class=\"now\" style=\"top: {:.3}%\"></div>\n",
+                    minutes.num_minutes() as f64 * 100.0 / (24.0 * 60.0)
+                ));
+            }
+            html.push_str("</div>\n");
+        }
+        html.push_str("</main>\n</body>\n</html>\n");
+        html
+    }
+
+    fn event_html(&self, event: &EventView, top: f64, height: f64) -> String {
+        let style = self.theme.event_style(event.color, EventPhase::Future);
+        let mut classes = vec!["event"];
+        match event.phase(self.now) {
+            EventPhase::Past => classes.push("past"),
+            EventPhase::Current => classes.push("current"),
+            EventPhase::Future => {}
+        }
+        if event.tentative {
+            classes.push("tentative");
+        }
+        let mut text = format!("{} {}", escape(&event.time), escape(&event.title));
+        for detail in &event.details {
+            text.push_str(&format!("<br>{}", escape(detail)));
+        }
+        format!(
+            "<div class=\"{}\" style=\"top: {:.3}%; height: {:.3}%; \
+             background-color: {}; color: {}\">{}</div>\n",
+            classes.join(" "),
+            top,
+            height,
+            style.bg.and_then(css_color).unwrap_or_default(),
+            style.fg.and_then(css_color).unwrap_or_default(),
+            text
+        )
+    }
+}
+
+fn css_color(color: Color) -> Option<String> {
+    theme::rgb(color).map(hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+
+    use super::*;
+
+    fn dashboard() -> Dashboard {
+        let at = |h, m| Tokyo.with_ymd_and_hms(2023, 10, 2, h, m, 0).unwrap();
+        let dashboard = Dashboard::default();
+        dashboard.publish(
+            &Theme::default(),
+            "2023-10-02 (Mon) 今日".to_string(),
+            at(10, 15),
+            &[DayColumn {
+                date: at(0, 0).date_naive(),
+                events: vec![EventView {
+                    title: "設計<レビュー>".to_string(),
+                    time: "10:00~12:00".to_string(),
+                    details: vec!["会議室A".to_string()],
+                    tentative: false,
+                    height: 4,
+                    color: Color::Blue,
+                    start: 20,
+                    start_time: at(10, 0),
+                    end_time: at(12, 0),
+                }],
+                now: Some(at(10, 15)),
+                selected: None,
+            }],
+        );
+        dashboard
+    }
+
+    #[test]
+    fn test_events_json() {
+        let response = dashboard().respond("/events.json?date=today");
+        assert_eq!(response.status, "200 OK");
+        let json: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        let event = &json["days"][0]["events"][0];
+        assert_eq!(json["days"][0]["date"], "2023-10-02");
+        assert_eq!(event["title"], "設計<レビュー>");
+        assert_eq!(event["start"], "2023-10-02T10:00:00+09:00");
+        assert_eq!(event["color"], "#0000ee");
+    }
+
+    #[test]
+    fn test_html() {
+        let html = dashboard().respond("/").body;
+        assert!(html.contains("設計&lt;レビュー&gt;"));
+        assert!(html.contains("class=\"event current\" style=\"top: 41.667%; height: 8.333%;"));
+
+        assert_eq!(
+            Dashboard::default().respond("/").status,
+            "503 Service Unavailable"
+        );
+        assert_eq!(dashboard().respond("/favicon.ico").status, "404 Not Found");
+    }
+
+    #[test]
+    fn test_metrics() {
+        let body = dashboard().respond("/metrics").body;
+        assert!(body.contains("today_calendar_events_displayed 1\n"));
+        let body = Dashboard::default().respond("/metrics").body;
+        assert!(body.contains("today_calendar_events_displayed 0\n"));
+    }
+
+    #[test]
+    fn test_serve_concurrently() {
+        use std::io::Read;
+
+        let address = dashboard().serve("127.0.0.1:0").unwrap();
+        // リクエストを送らずに止まっている接続があっても、次の接続にはすぐ応える
+        let _idle = TcpStream::connect(address).unwrap();
+        let started = std::time::Instant::now();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
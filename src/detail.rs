@@ -0,0 +1,200 @@
+use ratatui::layout::Rect;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+use ratatui::Frame;
+
+use crate::config::PrivacyConfig;
+use crate::event::{EventModel, EventTime};
+
+// 選択した予定の詳細を表示するポップアップ
+pub struct DetailPopup {
+    title: String,
+    lines: Vec<Line<'static>>,
+    scroll: u16,
+}
+
+impl DetailPopup {
+    pub fn new(event: &EventModel, privacy: &PrivacyConfig, scroll: u16) -> Self {
+        let data = event.data();
+        let mut lines = Vec::new();
+        let title = privacy.title(event);
+
+        let time = match (event.start(), event.end()) {
+            (Some(EventTime::DateTime(start)), Some(EventTime::DateTime(end))) => {
+                format!("{}~{}", start.format("%H:%M"), end.format("%H:%M"))
+            }
+            _ => "終日".to_string(),
+        };
+        Self::push_field(&mut lines, "時間", time);
+        Self::push_field(
+            &mut lines,
+            "カレンダー",
+            event.calendar().name().to_string(),
+        );
+        // 隠す予定は時間とカレンダーだけにする
+        if privacy.masks(event) {
+            return DetailPopup {
+                title,
+                lines,
+                scroll,
+            };
+        }
+        if !event.tags().is_empty() {
+            Self::push_field(&mut lines, "タグ", event.tags().join(", "));
+        }
+        if let Some(location) = event.location() {
+            Self::push_field(&mut lines, "場所", location.to_string());
+        }
+        if let Some(organizer) = &data.organizer {
+            if let Some(name) = organizer
+                .display_name
+                .clone()
+                .or_else(|| organizer.email.clone())
+            {
+                Self::push_field(&mut lines, "主催者", name);
+            }
+        }
+        if let Some(link) = event.conference_link() {
+            Self::push_field(&mut lines, "会議", link.to_string());
+        }
+        if let Some(attendees) = &data.attendees {
+            lines.push(Line::from(Span::styled(
+                "参加者:",
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            for attendee in attendees {
+                let name = attendee
+                    .display_name
+                    .as_deref()
+                    .or(attendee.email.as_deref())
+                    .unwrap_or_default();
+                let status = attendee.response_status.as_deref().unwrap_or("needsAction");
+                lines.push(Line::from(format!("  {} ({})", name, status)));
+            }
+        }
+        if let Some(description) = &data.description {
+            lines.push(Line::from(""));
+            lines.extend(
+                Self::strip_html(description)
+                    .lines()
+                    .map(|line| Line::from(line.to_string())),
+            );
+        }
+
+        DetailPopup {
+            title,
+            lines,
+            scroll,
+        }
+    }
+
+    fn push_field(lines: &mut Vec<Line<'static>>, label: &'static str, value: String) {
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("{}: ", label),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(value),
+        ]));
+    }
+
+    // Googleカレンダーの説明欄はHTMLで入っていることがあるので、改行だけ残してタグを落とす
+    fn strip_html(description: &str) -> String {
+        let description = description
+            .replace("<br>", "\n")
+            .replace("<br/>", "\n")
+            .replace("<br />", "\n");
+        let mut text = String::with_capacity(description.len());
+        let mut in_tag = false;
+        for c in description.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                _ if !in_tag => text.push(c),
+                _ => {}
+            }
+        }
+        text.replace("&nbsp;", " ")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&amp;", "&")
+    }
+
+    // 画面の中央、上下左右に1割ずつ余白を取った位置
+    fn popup_area(area: Rect) -> Rect {
+        Rect {
+            x: area.x + area.width / 10,
+            y: area.y + area.height / 10,
+            width: area.width - area.width / 5,
+            height: area.height - area.height / 5,
+        }
+    }
+
+    fn block(&self) -> Block<'static> {
+        Block::default()
+            .borders(Borders::ALL)
+            .title(self.title.clone())
+            .title_bottom("j/k:スクロール  Esc:閉じる")
+    }
+
+    // 最後の行が枠の下端に来るまでのスクロール量。それ以上は空白しか見えない
+    pub fn max_scroll(&self, area: Rect) -> u16 {
+        let inner = self.block().inner(Self::popup_area(area));
+        // 枠の内側の幅で折り返したときの行数を数える
+        let lines = Paragraph::new(self.lines.clone())
+            .wrap(Wrap { trim: false })
+            .line_count(inner.width);
+        (lines as u16).saturating_sub(inner.height)
+    }
+
+    pub fn render(self, frame: &mut Frame, style: Style) {
+        let popup_area = Self::popup_area(frame.area());
+        let block = self.block();
+        frame.render_widget(Clear, popup_area);
+        frame.render_widget(
+            Paragraph::new(self.lines)
+                .style(style)
+                .block(block)
+                .wrap(Wrap { trim: false })
+                .scroll((self.scroll, 0)),
+            popup_area,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use google_calendar3::api::Event;
+
+    use super::*;
+    use crate::calendar::Calendar;
+
+    #[test]
+    fn test_max_scroll() {
+        let description = (1..=30).map(|i| format!("{}行目", i)).collect::<Vec<_>>();
+        let event = EventModel::new(
+            Event {
+                description: Some(description.join("<br>")),
+                ..Default::default()
+            },
+            Calendar::Primary,
+        );
+        let popup = DetailPopup::new(&event, &PrivacyConfig::default(), 0);
+        // 時間とカレンダーと空行で3行、説明が30行。枠の内側は38x14
+        assert_eq!(popup.max_scroll(Rect::new(0, 0, 50, 20)), 33 - 14);
+        // 狭い画面では折り返す分だけ多くスクロールできる
+        assert!(popup.max_scroll(Rect::new(0, 0, 10, 20)) > 33 - 14);
+        // 全部入る大きさならスクロールしない
+        assert_eq!(popup.max_scroll(Rect::new(0, 0, 100, 60)), 0);
+    }
+
+    #[test]
+    fn test_strip_html() {
+        assert_eq!(
+            DetailPopup::strip_html("<b>議題</b><br>1. 進捗 &amp; 課題<br/><a href=\"x\">資料</a>"),
+            "議題\n1. 進捗 & 課題\n資料"
+        );
+    }
+}
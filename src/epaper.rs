@@ -0,0 +1,483 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::config::{EpaperColors, EpaperConfig};
+use crate::pixel::{Canvas, Rgb};
+
+// コントローラ (UC8179。Waveshareの7.5インチV2 / B V2が使っているもの) のコマンド
+const PANEL_SETTING: u8 = 0x00;
+const POWER_ON: u8 = 0x04;
+// 書き換え前の画像。3色のパネルでは黒の面になる
+const OLD_DATA: u8 = 0x10;
+const DISPLAY_REFRESH: u8 = 0x12;
+// 書き換え後の画像。3色のパネルでは赤の面になる
+const NEW_DATA: u8 = 0x13;
+const VCOM_DATA_INTERVAL: u8 = 0x50;
+const RESOLUTION: u8 = 0x61;
+const PARTIAL_WINDOW: u8 = 0x90;
+const PARTIAL_IN: u8 = 0x91;
+const PARTIAL_OUT: u8 = 0x92;
+
+// 全体の書き換えは十数秒かかることがある
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+// 電子ペーパーのコントローラとのやりとり。実機ではSPIとGPIO、テストでは送った内容を記録するだけのもの
+pub trait Spi {
+    fn reset(&mut self) -> io::Result<()>;
+    // コマンドを1バイト送り、続けてデータを送る
+    fn command(&mut self, command: u8, data: &[u8]) -> io::Result<()>;
+    // 書き換えが終わるまで待つ
+    fn wait_busy(&mut self) -> io::Result<()>;
+}
+
+// 1ビットの画像の面。1行は8ピクセルずつバイトに詰め、立っているビットがインクの乗るピクセル
+#[derive(Clone, PartialEq)]
+struct Plane {
+    row_bytes: usize,
+    bytes: Vec<u8>,
+}
+
+impl Plane {
+    fn new(width: u32, height: u32) -> Self {
+        let row_bytes = width.div_ceil(8) as usize;
+        Plane {
+            row_bytes,
+            bytes: vec![0; row_bytes * height as usize],
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32) {
+        self.bytes[y as usize * self.row_bytes + x as usize / 8] |= 0x80 >> (x % 8);
+    }
+
+    // 枠の中のバイト列。部分更新で送る
+    fn window(&self, window: &Window) -> Vec<u8> {
+        (window.top..window.bottom)
+            .flat_map(|y| {
+                let row = y * self.row_bytes;
+                self.bytes[row + window.left..row + window.right].iter()
+            })
+            .copied()
+            .collect()
+    }
+}
+
+// 黒の面と、3色のパネルなら赤の面
+#[derive(Clone, PartialEq)]
+struct Frame {
+    black: Plane,
+    red: Option<Plane>,
+}
+
+impl Frame {
+    // 明るさで白黒に分ける。3色のパネルでは赤っぽい色を赤にする
+    fn from_canvas(canvas: &Canvas, width: u32, height: u32, colors: EpaperColors) -> Self {
+        let mut black = Plane::new(width, height);
+        let mut red = (colors == EpaperColors::BlackWhiteRed).then(|| Plane::new(width, height));
+        for y in 0..height.min(canvas.height) {
+            for x in 0..width.min(canvas.width) {
+                let color = canvas.pixel(x, y);
+                match &mut red {
+                    Some(red) if is_red(color) => red.set(x, y),
+                    _ if luminance(color) < 128 => black.set(x, y),
+                    _ => {}
+                }
+            }
+        }
+        Frame { black, red }
+    }
+
+    // 前の画像と違うところを囲む枠。横はバイト (8ピクセル) 単位
+    fn changed(&self, previous: &Frame) -> Option<Window> {
+        let row_bytes = self.black.row_bytes;
+        let mut window: Option<Window> = None;
+        let planes = [(&self.black, &previous.black)]
+            .into_iter()
+            .chain(self.red.as_ref().zip(previous.red.as_ref()));
+        for (current, previous) in planes {
+            for (i, (a, b)) in current.bytes.iter().zip(&previous.bytes).enumerate() {
+                if a == b {
+                    continue;
+                }
+                let (y, x) = (i / row_bytes, i % row_bytes);
+                window = Some(match window {
+                    None => Window {
+                        left: x,
+                        right: x + 1,
+                        top: y,
+                        bottom: y + 1,
+                    },
+                    Some(window) => Window {
+                        left: window.left.min(x),
+                        right: window.right.max(x + 1),
+                        top: window.top.min(y),
+                        bottom: window.bottom.max(y + 1),
+                    },
+                });
+            }
+        }
+        window
+    }
+}
+
+fn luminance((r, g, b): Rgb) -> u32 {
+    (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000
+}
+
+fn is_red((r, g, b): Rgb) -> bool {
+    r >= 0x80 && (g as u32 + b as u32) < r as u32
+}
+
+// 部分更新する範囲。left, right はバイト単位、top, bottom は行 (どちらも right, bottom を含まない)
+#[derive(Debug, PartialEq)]
+struct Window {
+    left: usize,
+    right: usize,
+    top: usize,
+    bottom: usize,
+}
+
+impl Window {
+    // コマンドの引数は始点と終点のピクセル位置 (終点を含む) を上位・下位バイトで並べる
+    fn command_data(&self) -> Vec<u8> {
+        let x_start = (self.left * 8) as u16;
+        let x_end = (self.right * 8 - 1) as u16;
+        let y_start = self.top as u16;
+        let y_end = (self.bottom - 1) as u16;
+        [x_start, x_end, y_start, y_end]
+            .into_iter()
+            .flat_map(u16::to_be_bytes)
+            // 枠の外はそのまま残す
+            .chain([0x01])
+            .collect()
+    }
+}
+
+// 電子ペーパーへの描画。画面に出ている内容を覚えておき、変わったところだけを部分更新する
+// 部分更新を続けると前の表示がうっすら残るので、決まった回数ごとに全体を書き換える
+pub struct Epaper {
+    spi: Box<dyn Spi>,
+    pub width: u32,
+    pub height: u32,
+    colors: EpaperColors,
+    full_refresh_every: u32,
+    // 画面に出ている内容。まだ一度も書いていなければNone
+    shown: Option<Frame>,
+    partial_refreshes: u32,
+}
+
+impl Epaper {
+    // SPIのデバイス (/dev/spidev0.0 など) と [epaper] のGPIOで接続する
+    pub fn open(device: &Path, config: &EpaperConfig) -> Result<Self> {
+        let spi = LinuxSpi::open(device, config)?;
+        Ok(Self::new(Box::new(spi), config)?)
+    }
+
+    pub fn new(mut spi: Box<dyn Spi>, config: &EpaperConfig) -> io::Result<Self> {
+        let (width, height) = (config.width, config.height);
+        spi.reset()?;
+        spi.command(POWER_ON, &[])?;
+        spi.wait_busy()?;
+        let (panel_setting, vcom) = match config.colors {
+            EpaperColors::BlackWhite => (0x1f, 0x10),
+            EpaperColors::BlackWhiteRed => (0x0f, 0x11),
+        };
+        spi.command(PANEL_SETTING, &[panel_setting])?;
+        let [width_high, width_low] = (width as u16).to_be_bytes();
+        let [height_high, height_low] = (height as u16).to_be_bytes();
+        spi.command(
+            RESOLUTION,
+            &[width_high, width_low, height_high, height_low],
+        )?;
+        spi.command(VCOM_DATA_INTERVAL, &[vcom, 0x07])?;
+        Ok(Epaper {
+            spi,
+            width,
+            height,
+            colors: config.colors,
+            full_refresh_every: config.full_refresh_every,
+            shown: None,
+            partial_refreshes: 0,
+        })
+    }
+
+    // 毎分の描き直しでも画面が変わっていなければ何もしない
+    pub fn write(&mut self, canvas: &Canvas) -> io::Result<()> {
+        let frame = Frame::from_canvas(canvas, self.width, self.height, self.colors);
+        let window = match &self.shown {
+            Some(shown) => match frame.changed(shown) {
+                Some(window) => Some(window),
+                None => return Ok(()),
+            },
+            None => None,
+        };
+        match window {
+            // 3色のパネルは部分更新ができない
+            Some(window)
+                if frame.red.is_none() && self.partial_refreshes < self.full_refresh_every =>
+            {
+                self.partial_refresh(&frame, &window)?;
+                self.partial_refreshes += 1;
+            }
+            _ => {
+                self.full_refresh(&frame)?;
+                self.partial_refreshes = 0;
+            }
+        }
+        self.shown = Some(frame);
+        Ok(())
+    }
+
+    fn full_refresh(&mut self, frame: &Frame) -> io::Result<()> {
+        match &frame.red {
+            // 黒の面は白が1、赤の面は赤が1
+            Some(red) => {
+                self.spi.command(OLD_DATA, &invert(&frame.black.bytes))?;
+                self.spi.command(NEW_DATA, &red.bytes)?;
+            }
+            // 白黒は黒が1で、書き換え前後の両方を送る。前が無いときは同じものを送る
+            None => {
+                let old = self.shown.as_ref().unwrap_or(frame);
+                self.spi.command(OLD_DATA, &old.black.bytes)?;
+                self.spi.command(NEW_DATA, &frame.black.bytes)?;
+            }
+        }
+        self.spi.command(DISPLAY_REFRESH, &[])?;
+        self.spi.wait_busy()
+    }
+
+    fn partial_refresh(&mut self, frame: &Frame, window: &Window) -> io::Result<()> {
+        let old = match &self.shown {
+            Some(shown) => shown.black.window(window),
+            None => frame.black.window(window),
+        };
+        self.spi.command(PARTIAL_IN, &[])?;
+        self.spi.command(PARTIAL_WINDOW, &window.command_data())?;
+        self.spi.command(OLD_DATA, &old)?;
+        self.spi.command(NEW_DATA, &frame.black.window(window))?;
+        self.spi.command(DISPLAY_REFRESH, &[])?;
+        self.spi.wait_busy()?;
+        self.spi.command(PARTIAL_OUT, &[])
+    }
+}
+
+fn invert(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().map(|byte| !byte).collect()
+}
+
+// Linuxのspidevと、sysfsのGPIOでつないだパネル
+struct LinuxSpi {
+    device: File,
+    dc: Gpio,
+    reset: Gpio,
+    busy: Gpio,
+}
+
+impl LinuxSpi {
+    fn open(device: &Path, config: &EpaperConfig) -> Result<Self> {
+        Ok(LinuxSpi {
+            device: OpenOptions::new()
+                .write(true)
+                .open(device)
+                .with_context(|| format!("failed to open {}", device.display()))?,
+            dc: Gpio::export(config.dc_pin, "out")?,
+            reset: Gpio::export(config.reset_pin, "out")?,
+            busy: Gpio::export(config.busy_pin, "in")?,
+        })
+    }
+}
+
+impl Spi for LinuxSpi {
+    fn reset(&mut self) -> io::Result<()> {
+        for (value, wait) in [(true, 20), (false, 2), (true, 20)] {
+            self.reset.set(value)?;
+            std::thread::sleep(Duration::from_millis(wait));
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, command: u8, data: &[u8]) -> io::Result<()> {
+        self.dc.set(false)?;
+        self.device.write_all(&[command])?;
+        if !data.is_empty() {
+            self.dc.set(true)?;
+            // spidevが1回に送れる大きさ (既定4096バイト) ずつ書く
+            for chunk in data.chunks(4096) {
+                self.device.write_all(chunk)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn wait_busy(&mut self) -> io::Result<()> {
+        // BUSYは書き換え中にLになる
+        let deadline = Instant::now() + BUSY_TIMEOUT;
+        while !self.busy.get()? {
+            if Instant::now() > deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "e-paper stayed busy",
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    }
+}
+
+struct Gpio {
+    value: PathBuf,
+}
+
+impl Gpio {
+    fn export(pin: u32, direction: &str) -> Result<Self> {
+        let sysfs = PathBuf::from(format!("/sys/class/gpio/gpio{}", pin));
+        if !sysfs.exists() {
+            std::fs::write("/sys/class/gpio/export", pin.to_string())
+                .with_context(|| format!("failed to export GPIO {}", pin))?;
+            // udevが権限を設定するまで少し待つ
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        std::fs::write(sysfs.join("direction"), direction)
+            .with_context(|| format!("failed to set direction of GPIO {}", pin))?;
+        Ok(Gpio {
+            value: sysfs.join("value"),
+        })
+    }
+
+    fn set(&self, high: bool) -> io::Result<()> {
+        std::fs::write(&self.value, if high { "1" } else { "0" })
+    }
+
+    fn get(&self) -> io::Result<bool> {
+        Ok(std::fs::read_to_string(&self.value)?.trim() == "1")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    // 送ったコマンドとデータ
+    type Recorded = Vec<(u8, Vec<u8>)>;
+
+    // 送った内容を記録するだけのSPI
+    #[derive(Clone, Default)]
+    struct MockSpi {
+        commands: Rc<RefCell<Recorded>>,
+    }
+
+    impl MockSpi {
+        fn take(&self) -> Recorded {
+            self.commands.take()
+        }
+    }
+
+    impl Spi for MockSpi {
+        fn reset(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn command(&mut self, command: u8, data: &[u8]) -> io::Result<()> {
+            self.commands.borrow_mut().push((command, data.to_vec()));
+            Ok(())
+        }
+
+        fn wait_busy(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn epaper(colors: EpaperColors) -> (Epaper, MockSpi) {
+        let spi = MockSpi::default();
+        let config = EpaperConfig {
+            width: 32,
+            height: 4,
+            colors,
+            full_refresh_every: 2,
+            ..EpaperConfig::default()
+        };
+        let epaper = Epaper::new(Box::new(spi.clone()), &config).unwrap();
+        spi.take();
+        (epaper, spi)
+    }
+
+    fn white_canvas() -> Canvas {
+        let mut canvas = Canvas::new(32, 4);
+        canvas.fill(0, 0, 32, 4, (0xff, 0xff, 0xff));
+        canvas
+    }
+
+    fn commands(recorded: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        recorded.iter().map(|(command, _)| *command).collect()
+    }
+
+    #[test]
+    fn test_partial_refresh_only_changes() {
+        let (mut epaper, spi) = epaper(EpaperColors::BlackWhite);
+        let mut canvas = white_canvas();
+
+        // 最初は全体を書き換える
+        epaper.write(&canvas).unwrap();
+        let recorded = spi.take();
+        assert_eq!(commands(&recorded), [OLD_DATA, NEW_DATA, DISPLAY_REFRESH]);
+        assert_eq!(recorded[1].1, vec![0; 16]);
+
+        // 変わっていなければ何も送らない
+        epaper.write(&canvas).unwrap();
+        assert!(spi.take().is_empty());
+
+        // 変わったところを含むバイトと行だけを送る
+        canvas.fill(9, 2, 2, 1, (0, 0, 0));
+        epaper.write(&canvas).unwrap();
+        let recorded = spi.take();
+        assert_eq!(
+            commands(&recorded),
+            [
+                PARTIAL_IN,
+                PARTIAL_WINDOW,
+                OLD_DATA,
+                NEW_DATA,
+                DISPLAY_REFRESH,
+                PARTIAL_OUT
+            ]
+        );
+        assert_eq!(recorded[1].1, [0, 8, 0, 15, 0, 2, 0, 2, 1]);
+        assert_eq!(recorded[2].1, [0]);
+        assert_eq!(recorded[3].1, [0b0110_0000]);
+
+        // 部分更新を full_refresh_every 回続けたら全体を書き換える
+        canvas.fill(0, 0, 1, 1, (0, 0, 0));
+        epaper.write(&canvas).unwrap();
+        assert_eq!(commands(&spi.take())[0], PARTIAL_IN);
+        canvas.fill(1, 0, 1, 1, (0, 0, 0));
+        epaper.write(&canvas).unwrap();
+        assert_eq!(commands(&spi.take()), [OLD_DATA, NEW_DATA, DISPLAY_REFRESH]);
+    }
+
+    #[test]
+    fn test_three_colors() {
+        let (mut epaper, spi) = epaper(EpaperColors::BlackWhiteRed);
+        let mut canvas = white_canvas();
+        canvas.fill(0, 0, 8, 1, (0xcd, 0, 0));
+        canvas.fill(8, 0, 8, 1, (0, 0, 0));
+        epaper.write(&canvas).unwrap();
+        let recorded = spi.take();
+        assert_eq!(commands(&recorded), [OLD_DATA, NEW_DATA, DISPLAY_REFRESH]);
+        // 黒の面は白が1、赤の面は赤が1
+        assert_eq!(recorded[0].1[..2], [0xff, 0x00]);
+        assert_eq!(recorded[1].1[..2], [0xff, 0x00]);
+
+        // 3色のパネルは変わったときも全体を書き換える
+        canvas.fill(16, 0, 8, 1, (0, 0, 0));
+        epaper.write(&canvas).unwrap();
+        assert_eq!(commands(&spi.take())[0], OLD_DATA);
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+
+// 予定取得時のエラー。表示側やスクリプト向けの終了コードで原因を区別するために分類している
+#[derive(Debug)]
+pub enum FetchError {
+    Auth(String),
+    // APIやトークンのリフレッシュで通信できなかったとき
+    Network(Box<dyn std::error::Error + Send + Sync>),
+    Http { status: StatusCode, body: String },
+    Parse(serde_json::Error),
+}
+
+impl FetchError {
+    // スクリプトから原因を判別できるようにするための終了コード
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FetchError::Auth(_) => 2,
+            FetchError::Network(_) => 3,
+            FetchError::Http { .. } | FetchError::Parse(_) => 4,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Auth(message) => write!(f, "authentication failed: {}", message),
+            FetchError::Network(e) => write!(f, "network error: {}", e),
+            FetchError::Http { status, body } => {
+                write!(f, "request failed with status {}: {}", status, body)
+            }
+            FetchError::Parse(e) => write!(f, "failed to parse response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Network(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> Self {
+        FetchError::Parse(e)
+    }
+}
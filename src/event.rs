@@ -1,4 +1,4 @@
-use chrono::Timelike;
+use chrono::{DateTime, NaiveDate, Timelike};
 use chrono_tz::{Asia::Tokyo, Tz};
 use ratatui::style::Color;
 
@@ -10,23 +10,219 @@ use anyhow::Result;
 pub struct EventModel {
     data: google_calendar3::api::Event,
     calendar_id: Calendar,
+    // Googleカレンダーで予定ごとに色を変えている場合の色 (colorIdを解決したもの)
+    event_color: Option<Color>,
+    // [[rules]] で書き換えたタイトル、色、付けたタグ
+    title: Option<String>,
+    rule_color: Option<Color>,
+    tags: Vec<String>,
 }
 
 impl EventModel {
     pub fn new(data: google_calendar3::api::Event, calendar_id: Calendar) -> Self {
-        EventModel { data, calendar_id }
+        EventModel {
+            data,
+            calendar_id,
+            event_color: None,
+            title: None,
+            rule_color: None,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn with_event_color(self, event_color: Option<Color>) -> Self {
+        EventModel {
+            event_color,
+            ..self
+        }
+    }
+
+    pub fn event_color(&self) -> Option<Color> {
+        self.event_color
+    }
+
+    pub fn with_title(self, title: String) -> Self {
+        EventModel {
+            title: Some(title),
+            ..self
+        }
+    }
+
+    pub fn with_rule_color(self, rule_color: Color) -> Self {
+        EventModel {
+            rule_color: Some(rule_color),
+            ..self
+        }
+    }
+
+    pub fn with_tag(mut self, tag: String) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    pub fn rule_color(&self) -> Option<Color> {
+        self.rule_color
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn data(&self) -> &google_calendar3::api::Event {
+        &self.data
+    }
+
+    pub fn calendar(&self) -> &Calendar {
+        &self.calendar_id
+    }
+
+    pub fn summary(&self) -> Option<&str> {
+        self.data.summary.as_deref()
+    }
+
+    // 表示用のタイトル。「予定の有無のみ」で共有されたカレンダーの予定は時間しか返ってこない
+    pub fn title(&self) -> String {
+        if let Some(title) = &self.title {
+            return title.clone();
+        }
+        match self.summary().filter(|summary| !summary.trim().is_empty()) {
+            Some(summary) => summary.to_string(),
+            // 主催者も分からない予定は予定の有無だけが共有されている
+            None if self.data.organizer.is_none() => "(busy)".to_string(),
+            None => "(no title)".to_string(),
+        }
+    }
+
+    // 公開範囲が「非公開」の予定
+    pub fn is_private(&self) -> bool {
+        matches!(
+            self.data.visibility.as_deref(),
+            Some("private") | Some("confidential")
+        )
+    }
+
+    // 自分の出欠 ("accepted", "declined", "tentative", "needsAction")。招待されていない予定はNone
+    pub fn response_status(&self) -> Option<&str> {
+        self.data
+            .attendees
+            .as_ref()?
+            .iter()
+            .find(|attendee| attendee.self_ == Some(true))
+            .and_then(|attendee| attendee.response_status.as_deref())
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.data.status.as_deref() == Some("cancelled")
+    }
+
+    // 「予定あり」ではなく「空き時間」として登録された予定
+    pub fn is_free(&self) -> bool {
+        self.data.transparency.as_deref() == Some("transparent")
+    }
+
+    // 開始の何分前に知らせるか。カレンダー既定の通知を使う予定はdefault_minutesにする
+    pub fn reminder_minutes(&self, default_minutes: i64) -> Vec<i64> {
+        match &self.data.reminders {
+            Some(reminders) if reminders.use_default == Some(false) => reminders
+                .overrides
+                .iter()
+                .flatten()
+                .filter_map(|reminder| reminder.minutes)
+                .map(i64::from)
+                .collect(),
+            _ => vec![default_minutes],
+        }
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.data.location.as_deref()
+    }
+
+    // 会議室などの設備の予約。参加者のうちresourceが立っているものの名前を使う
+    pub fn room(&self) -> Option<&str> {
+        self.data
+            .attendees
+            .as_ref()?
+            .iter()
+            .find(|attendee| attendee.resource == Some(true))
+            .and_then(|attendee| attendee.display_name.as_deref())
+    }
+
+    // Meetなどのビデオ会議のURL。conferenceDataが無い古い予定はhangoutLinkだけ入っている
+    pub fn conference_link(&self) -> Option<&str> {
+        self.data
+            .conference_data
+            .as_ref()
+            .and_then(|conference| conference.entry_points.as_ref())
+            .and_then(|entry_points| {
+                entry_points
+                    .iter()
+                    .find(|entry_point| entry_point.entry_point_type.as_deref() == Some("video"))
+            })
+            .and_then(|entry_point| entry_point.uri.as_deref())
+            .or(self.data.hangout_link.as_deref())
+    }
+
+    // 終日予定はdate、時刻指定の予定はdate_timeだけが入っている
+    pub fn is_all_day(&self) -> bool {
+        self.data
+            .start
+            .as_ref()
+            .is_some_and(|start| start.date_time.is_none() && start.date.is_some())
+    }
+
+    pub fn start(&self) -> Option<EventTime> {
+        EventTime::from_api(self.data.start.as_ref()?)
+    }
+
+    pub fn end(&self) -> Option<EventTime> {
+        EventTime::from_api(self.data.end.as_ref()?)
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventTime {
+    AllDay(NaiveDate),
+    DateTime(DateTime<Tz>),
+}
+
+impl EventTime {
+    fn from_api(time: &google_calendar3::api::EventDateTime) -> Option<Self> {
+        match (time.date_time, time.date) {
+            (Some(date_time), _) => Some(EventTime::DateTime(date_time.with_timezone(&Tokyo))),
+            (None, Some(date)) => Some(EventTime::AllDay(date)),
+            (None, None) => None,
+        }
+    }
+}
+
+// 現在時刻から見た予定の状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventPhase {
+    Past,
+    Current,
+    Future,
+}
+
+#[derive(Debug, Clone)]
 pub struct EventView {
     pub title: String,
+    // "10:00~11:00" の形の時間帯。枠の大きさに合わせてタイトルと別の行に置けるよう分けておく
+    pub time: String,
+    // 場所や会議のURLなど、高さに余裕があるときだけタイトルの下に出す行
+    pub details: Vec<String>,
+    // 出欠を「未定」にしている予定は斜線で塗って区別する
+    pub tentative: bool,
     pub height: u16,
     pub color: Color,
     pub start: u16,
+    pub start_time: DateTime<Tz>,
+    pub end_time: DateTime<Tz>,
 }
 
 impl EventView {
-    pub fn from_event(event: EventModel) -> Result<Self> {
+    // dateの列に表示する予定の情報を作る。日をまたぐ予定はその日の範囲に切り詰める
+    pub fn from_event(event: EventModel, date: NaiveDate) -> Result<Self> {
         let start_time = event
             .data
             .start
@@ -46,25 +242,58 @@ impl EventView {
             .ok_or_else(|| anyhow::anyhow!("end time is not defined"))?
             .with_timezone(&Tokyo);
 
-        let start_height = Self::date_time_to_height(start_time, &Tokyo);
-        let event_height = match Self::date_time_to_height(end_time, &Tokyo) {
-            0 => 48,
-            x => x,
-        } - start_height;
+        let day_start = date.and_time(chrono::NaiveTime::MIN);
+        if start_time.date_naive() > date || end_time.naive_local() <= day_start {
+            return Err(anyhow::anyhow!("event is not on {}", date));
+        }
+        let start_height = if start_time.date_naive() < date {
+            0
+        } else {
+            Self::date_time_to_height(start_time, &Tokyo)
+        };
+        let event_height = if end_time.date_naive() > date {
+            48
+        } else {
+            match Self::date_time_to_height(end_time, &Tokyo) {
+                0 => 48,
+                x => x,
+            }
+        }
+        .saturating_sub(start_height);
 
         Ok(EventView {
-            title: (format!(
-                "{} {}~{}",
-                event.data.summary.unwrap(),
+            // タグは "[採用] 面接" のようにタイトルの前に付ける
+            title: event
+                .tags()
+                .iter()
+                .map(|tag| format!("[{}] ", tag))
+                .chain([event.title()])
+                .collect(),
+            time: format!(
+                "{}~{}",
                 start_time.format("%H:%M"),
                 end_time.format("%H:%M")
-            )),
+            ),
+            details: Vec::new(),
+            tentative: event.response_status() == Some("tentative"),
             height: event_height.max(1),
             color: event.calendar_id.color(),
-            start: start_height as u16,
+            start: start_height,
+            start_time,
+            end_time,
         })
     }
 
+    pub fn phase(&self, now: DateTime<Tz>) -> EventPhase {
+        if self.end_time <= now {
+            EventPhase::Past
+        } else if self.start_time <= now {
+            EventPhase::Current
+        } else {
+            EventPhase::Future
+        }
+    }
+
     // DateTimeからUI用の高さに変換。
     /*
     example:
@@ -111,4 +340,61 @@ mod tests {
         let dt = tz.with_ymd_and_hms(2023, 10, 1, 0, 0, 0).unwrap();
         assert_eq!(EventView::date_time_to_height(dt, &tz), 0);
     }
+
+    #[test]
+    fn test_from_event_clips_to_date() {
+        use google_calendar3::api::{Event, EventDateTime};
+
+        let at = |d, h| {
+            Some(EventDateTime {
+                date_time: Some(
+                    Tokyo
+                        .with_ymd_and_hms(2023, 10, d, h, 0, 0)
+                        .unwrap()
+                        .to_utc(),
+                ),
+                ..Default::default()
+            })
+        };
+        // 10/1 22:00 ~ 10/2 02:00 の予定
+        let event = EventModel::new(
+            Event {
+                summary: Some("夜勤".to_string()),
+                start: at(1, 22),
+                end: at(2, 2),
+                ..Default::default()
+            },
+            Calendar::Primary,
+        );
+        let date = |d| chrono::NaiveDate::from_ymd_opt(2023, 10, d).unwrap();
+
+        let first = EventView::from_event(event.clone(), date(1)).unwrap();
+        assert_eq!((first.start, first.height), (44, 4));
+
+        let second = EventView::from_event(event.clone(), date(2)).unwrap();
+        assert_eq!((second.start, second.height), (0, 4));
+
+        assert!(EventView::from_event(event, date(3)).is_err());
+    }
+
+    #[test]
+    fn test_title_without_summary() {
+        let event = |summary: Option<&str>, organizer| {
+            EventModel::new(
+                google_calendar3::api::Event {
+                    summary: summary.map(str::to_string),
+                    organizer,
+                    ..Default::default()
+                },
+                Calendar::Primary,
+            )
+        };
+        assert_eq!(event(Some("定例"), None).title(), "定例");
+        // 予定の有無だけ共有されたカレンダー
+        assert_eq!(event(None, None).title(), "(busy)");
+        assert_eq!(
+            event(Some(""), Some(Default::default())).title(),
+            "(no title)"
+        );
+    }
 }
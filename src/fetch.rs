@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Instant;
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveTime};
+use chrono_tz::Tz;
+use oauth2::url::Url;
+use ratatui::style::Color;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+use crate::calendar::Calendar;
+use crate::error::FetchError;
+use crate::event::EventModel;
+use crate::metrics::Metrics;
+use crate::token::Token;
+
+// dateの日からdays日分の予定を取得するもの。Appのテストでは通信しないものに差し替える
+pub trait FetchEvents: Send {
+    fn fetch_date_events(
+        &mut self,
+        date: DateTime<Tz>,
+        days: u32,
+    ) -> Result<Vec<EventModel>, FetchError>;
+}
+
+// Google Calendar APIから予定を取得する。通信はblockingなので、TUIからは別スレッドで呼ぶ
+pub struct Fetcher {
+    token: Token,
+    calendar_list: Vec<Calendar>,
+    client: Client,
+    event_colors: Option<HashMap<String, Color>>,
+    metrics: Metrics,
+}
+
+impl Fetcher {
+    pub fn new(
+        client_id: String,
+        client_secret: String,
+        calendar_list: Vec<Calendar>,
+        interactive: bool,
+    ) -> Result<Self> {
+        let metrics = Metrics::default();
+        Ok(Fetcher {
+            token: Token::new(client_id, client_secret, interactive, metrics.clone())?,
+            calendar_list,
+            client: Client::new(),
+            event_colors: None,
+            metrics,
+        })
+    }
+
+    // 取得の状況。Fetcherが別スレッドに移ってからも読めるよう共有する
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    fn get_utc_date_range_string(date: DateTime<Tz>, days: u32) -> (String, String) {
+        let last = date + chrono::Days::new((days.max(1) - 1).into());
+        (
+            date.with_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
+                .unwrap()
+                .to_rfc3339(),
+            last.with_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap())
+                .unwrap()
+                .to_rfc3339(),
+        )
+    }
+
+    // dateの日からdays日分の予定を、全カレンダー分まとめて時刻順で返す
+    pub fn fetch_date_events(
+        &mut self,
+        date: DateTime<Tz>,
+        days: u32,
+    ) -> Result<Vec<EventModel>, FetchError> {
+        // 複数日表示でもカレンダーごとに1回のリクエストでまとめて取得する
+        let (time_min, time_max) = Fetcher::get_utc_date_range_string(date, days);
+
+        // 色の一覧の取得はカレンダーごとの取得時間に含めない
+        let event_colors = self.event_colors();
+        let mut events = Vec::new();
+        for calendar in self.calendar_list.clone() {
+            let started_at = Instant::now();
+            let result = self.fetch_calendar_events(&calendar, &time_min, &time_max, &event_colors);
+            self.metrics
+                .record_fetch(&calendar, started_at.elapsed(), &result);
+            events.extend(result?);
+        }
+        // カレンダーをまたいで時刻順に並べておくと、選択の移動が上から順になる
+        events.sort_by_key(|event| event.start());
+        Ok(events)
+    }
+
+    fn fetch_calendar_events(
+        &mut self,
+        calendar: &Calendar,
+        time_min: &str,
+        time_max: &str,
+        event_colors: &HashMap<String, Color>,
+    ) -> Result<Vec<EventModel>, FetchError> {
+        let url = Url::parse_with_params(
+            format!(
+                "https://www.googleapis.com/calendar/v3/calendars/{}/events",
+                calendar.id()
+            )
+            .as_str(),
+            &[
+                ("timeMin", time_min),
+                ("timeMax", time_max),
+                ("orderBy", "startTime"),
+                ("singleEvents", "true"),
+                ("access_type", "offline"),
+                ("prompt", "consent"),
+            ],
+        )
+        .expect("URL should be valid");
+
+        let response_data = self.get::<google_calendar3::api::Events>(url)?;
+        Ok(response_data
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .map(|event| {
+                let color = event
+                    .color_id
+                    .as_ref()
+                    .and_then(|id| event_colors.get(id))
+                    .copied();
+                EventModel::new(event, calendar.clone()).with_event_color(color)
+            })
+            .collect())
+    }
+
+    // 予定ごとに設定された色 (colorId) の一覧。変わることはほぼ無いので一度取得したら使い回す
+    fn event_colors(&mut self) -> HashMap<String, Color> {
+        if let Some(colors) = &self.event_colors {
+            return colors.clone();
+        }
+        let url = Url::parse("https://www.googleapis.com/calendar/v3/colors")
+            .expect("URL should be valid");
+        // 取得できなくてもカレンダーの色で表示できるので、失敗は数えるだけにして次の取得で再試行する
+        let response_data = match self.get::<google_calendar3::api::Colors>(url) {
+            Ok(response_data) => response_data,
+            Err(e) => {
+                self.metrics.record_error(&e);
+                return HashMap::new();
+            }
+        };
+        let colors: HashMap<String, Color> = response_data
+            .event
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(id, definition)| {
+                Some((id, Color::from_str(definition.background.as_deref()?).ok()?))
+            })
+            .collect();
+        self.event_colors = Some(colors.clone());
+        colors
+    }
+
+    // アクセストークンが切れていたらリフレッシュして1回だけやり直す
+    fn get<T: DeserializeOwned>(&mut self, url: Url) -> Result<T, FetchError> {
+        let client = &self.client;
+        let send = |access_token: &str| client.get(url.clone()).bearer_auth(access_token).send();
+
+        let mut response = send(&self.token.access_token)?;
+
+        if response.status() == StatusCode::UNAUTHORIZED {
+            self.token.refresh()?;
+            response = send(&self.token.access_token)?;
+            if response.status() == StatusCode::UNAUTHORIZED {
+                return Err(FetchError::Auth(
+                    "request is still unauthorized after token refresh".to_string(),
+                ));
+            }
+        }
+
+        if !response.status().is_success() {
+            return Err(FetchError::Http {
+                status: response.status(),
+                body: response.text().unwrap_or_default(),
+            });
+        }
+
+        let response_text = response.text()?;
+        Ok(serde_json::from_str::<T>(response_text.as_str())?)
+    }
+}
+
+impl FetchEvents for Fetcher {
+    fn fetch_date_events(
+        &mut self,
+        date: DateTime<Tz>,
+        days: u32,
+    ) -> Result<Vec<EventModel>, FetchError> {
+        Fetcher::fetch_date_events(self, date, days)
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+
+use crate::app::App;
+use crate::bdf::BdfFont;
+use crate::pixel::{Output, PixelBackend};
+use crate::svg;
+
+// フォントを読めなかったときのSVGのセルの大きさ
+const SVG_CELL: (u32, u32) = (8, 16);
+
+// 予定の表示を画像に書き出す。TUIと同じ render_ui でセルに描いてから画像にするので、画面と同じ配置になる
+// 形式はファイルの拡張子 (.png / .svg) で決める
+pub fn write(app: &App, path: &Path, width: u32, height: u32, font: &Path) -> Result<()> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => {
+            let output = Output::Png {
+                path: path.to_path_buf(),
+                width,
+                height,
+            };
+            // 描き終わったときのflushでPNGが書き出される
+            let mut terminal = Terminal::new(PixelBackend::new(BdfFont::load(font)?, output))?;
+            app.redraw(&mut terminal)
+        }
+        Some("svg") => {
+            // SVGの文字はフォントで描くので、ビットマップフォントはセルの大きさを決めるのにだけ使う
+            let cell = BdfFont::load(font)
+                .map(|font| (font.cell_width, font.cell_height))
+                .unwrap_or(SVG_CELL);
+            let svg = render_svg(width, height, cell, |terminal| app.redraw(terminal))?;
+            Ok(std::fs::write(path, svg)?)
+        }
+        _ => Err(anyhow!(
+            "unsupported image format (use .png or .svg): {}",
+            path.display()
+        )),
+    }
+}
+
+fn render_svg(
+    width: u32,
+    height: u32,
+    (cell_width, cell_height): (u32, u32),
+    draw: impl FnOnce(&mut Terminal<TestBackend>) -> Result<()>,
+) -> Result<String> {
+    let mut terminal = Terminal::new(TestBackend::new(
+        (width / cell_width) as u16,
+        (height / cell_height) as u16,
+    ))?;
+    draw(&mut terminal)?;
+    Ok(svg::render(
+        terminal.backend().buffer(),
+        cell_width,
+        cell_height,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+    use ratatui::style::Color;
+
+    use super::*;
+    use crate::config::LayoutMode;
+    use crate::event::EventView;
+    use crate::theme::Theme;
+    use crate::ui::{self, DayColumn};
+
+    // 期待する出力は src/snapshots/ に置いている。表示を意図して変えたときは
+    // UPDATE_SNAPSHOTS=1 cargo test で書き直し、差分を確認してからコミットする
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/snapshots")
+            .join(name);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            expected == actual,
+            "{} does not match the rendered output",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn test_day_view_snapshot() {
+        let at = |h, m| Tokyo.with_ymd_and_hms(2023, 10, 2, h, m, 0).unwrap();
+        // 30分ごとの枠の番号を時刻に直す
+        let slot = |slot: u16| at((slot / 2).into(), (slot % 2 * 30).into());
+        let event = |title: &str, start: u16, end: u16, color| EventView {
+            title: title.to_string(),
+            time: format!(
+                "{}~{}",
+                slot(start).format("%H:%M"),
+                slot(end).format("%H:%M")
+            ),
+            details: vec!["会議室A".to_string()],
+            tentative: false,
+            height: end - start,
+            color,
+            start,
+            start_time: slot(start),
+            end_time: slot(end),
+        };
+        let columns = vec![DayColumn {
+            date: at(0, 0).date_naive(),
+            events: vec![
+                event("朝会", 18, 19, Color::Red),
+                event("設計レビュー", 20, 24, Color::Blue),
+                event("1on1", 28, 30, Color::Green),
+            ],
+            now: Some(at(10, 15)),
+            selected: None,
+        }];
+
+        let svg = render_svg(400, 800, (8, 16), |terminal| {
+            ui::render_ui(
+                terminal,
+                LayoutMode::Vertical,
+                &Theme::default(),
+                at(10, 15),
+                "2023-10-02 (Mon) 今日".to_string(),
+                None,
+                1,
+                columns,
+                None,
+            )
+        })
+        .unwrap();
+        assert_snapshot("day.svg", &svg);
+    }
+}
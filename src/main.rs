@@ -1,35 +1,160 @@
+mod agenda;
+mod app;
+mod bdf;
+mod blank;
 mod calendar;
+mod chime;
+mod config;
+mod dashboard;
+mod detail;
+mod epaper;
+mod error;
 mod event;
+mod fetch;
+mod image;
+mod metrics;
+mod pixel;
+mod png;
+mod process;
+mod reminder;
+mod rules;
+mod supervisor;
+mod svg;
+mod terminal;
+mod text;
+mod theme;
 mod token;
-use std::{env, io, time::Duration};
+mod ui;
+use std::env;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use agenda::AgendaFormat;
 use anyhow::Result;
+use app::{App, AppEvent};
+use bdf::BdfFont;
 use calendar::Calendar;
-use chrono::{DateTime, Datelike, Timelike, Utc};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use chrono_tz::Asia::Tokyo;
 use chrono_tz::Tz;
-use event::{EventModel, EventView};
+use clap::{Parser, Subcommand};
+use config::Config;
+use dashboard::Dashboard;
+use epaper::Epaper;
+use error::FetchError;
+use fetch::{FetchEvents, Fetcher};
 use oauth2::basic::{BasicErrorResponseType, BasicTokenType};
-use oauth2::url::Url;
 use oauth2::{
     EmptyExtraTokenFields, EndpointNotSet, EndpointSet, RevocationErrorResponseType,
     StandardErrorResponse, StandardRevocableToken, StandardTokenIntrospectionResponse,
     StandardTokenResponse,
 };
-use ratatui::crossterm;
-use ratatui::layout::Rect;
-use ratatui::prelude::CrosstermBackend;
-use ratatui::style::Style;
-use ratatui::widgets::{Block, Borders, Paragraph};
+use pixel::{Framebuffer, Output, PixelBackend};
+use ratatui::backend::Backend;
 use ratatui::Terminal;
-use reqwest::blocking::Client;
-use token::Token;
-
-struct App {
-    events: Option<Vec<EventModel>>,
-    token: Token,
-    fetched_time: DateTime<Tz>,
-    calendar_list: Vec<Calendar>,
+use terminal::TerminalGuard;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "今日のGoogleカレンダーの予定を表示する")]
+struct Cli {
+    /// TUIを起動せずに予定を標準出力へ書き出す
+    #[arg(long, value_enum)]
+    output: Option<AgendaFormat>,
+
+    /// 表示する日付 (YYYY-MM-DD)。省略時は今日
+    #[arg(long)]
+    date: Option<NaiveDate>,
+
+    /// 操作がないまま指定秒数が経つと今日の表示に戻る
+    #[arg(long, default_value_t = 300)]
+    idle_timeout: u64,
+
+    /// 設定ファイルのパス
+    #[arg(long, default_value = "config.toml")]
+    config: PathBuf,
+
+    /// 横に並べて表示する日数。`w` で1日表示と切り替えられる
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=14))]
+    days: u32,
+
+    /// 端末を使わずにフレームバッファ (/dev/fb0 など) へ直接描く
+    #[arg(long, conflicts_with_all = ["png", "epaper"])]
+    framebuffer: Option<PathBuf>,
+
+    /// 端末を使わずにSPI (/dev/spidev0.0 など) でつないだ電子ペーパーへ描く。配線は設定ファイルの [epaper]
+    #[arg(long, conflicts_with = "png")]
+    epaper: Option<PathBuf>,
+
+    /// 端末を使わずに、描画のたびにPNGへ書き出す (動作確認用)
+    #[arg(long)]
+    png: Option<PathBuf>,
+
+    /// 描画のたびに時刻を書き込むファイル。固まっていないかの監視に使う
+    #[arg(long)]
+    heartbeat: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// TUIを子プロセスとして起動し、落ちたり描画が止まったりしたら起動し直す
+    Supervise {
+        /// 描画がこの分数止まったら固まったとみなす
+        #[arg(long, default_value_t = 3)]
+        hang_minutes: u64,
+
+        /// TUIに書かせる生存確認用のファイル
+        #[arg(long, default_value = "heartbeat")]
+        heartbeat: PathBuf,
+    },
+    /// superviseで起動するsystemdのユニットファイルを標準出力に書き出す
+    SystemdUnit {
+        /// 表示に使う端末
+        #[arg(long, default_value = "/dev/tty1")]
+        tty: String,
+    },
+    /// 予定の表示をPNGかSVGの画像に書き出す。形式はファイルの拡張子で決める
+    Render {
+        /// 書き出す画像のパス (.png / .svg)
+        path: PathBuf,
+
+        /// 画像の幅 (ピクセル)。省略時は設定ファイルの [pixel] の値
+        #[arg(long)]
+        width: Option<u32>,
+
+        /// 画像の高さ (ピクセル)。省略時は設定ファイルの [pixel] の値
+        #[arg(long)]
+        height: Option<u32>,
+    },
+}
+
+impl Cli {
+    // superviseから起動するTUIに引き継ぐオプション
+    fn child_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            "--idle-timeout".into(),
+            self.idle_timeout.to_string().into(),
+            "--config".into(),
+            self.config.clone().into(),
+            "--days".into(),
+            self.days.to_string().into(),
+        ];
+        if let Some(device) = &self.framebuffer {
+            args.extend(["--framebuffer".into(), device.clone().into()]);
+        }
+        if let Some(device) = &self.epaper {
+            args.extend(["--epaper".into(), device.clone().into()]);
+        }
+        if let Some(path) = &self.png {
+            args.extend(["--png".into(), path.clone().into()]);
+        }
+        args
+    }
 }
 
 type OAuthClient = oauth2::Client<
@@ -45,279 +170,298 @@ type OAuthClient = oauth2::Client<
     EndpointSet,
 >;
 
-impl App {
-    fn new(
-        client_id: String,
-        client_secret: String,
-        now: DateTime<Tz>,
-        calendar_list: Vec<Calendar>,
-    ) -> Result<Self> {
-        Ok(App {
-            events: None,
-            token: Token::new(client_id, client_secret)?,
-            fetched_time: now,
-            calendar_list,
-        })
-    }
+pub fn calendar_list() -> Vec<Calendar> {
+    vec![Calendar::Primary, Calendar::Private, Calendar::University]
+}
 
-    fn get_utc_date_range_string(date: DateTime<Tz>) -> Result<(String, String)> {
-        return Ok((
-            date.with_time(chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-                .unwrap()
-                .to_rfc3339(),
-            date.with_time(chrono::NaiveTime::from_hms_opt(23, 59, 59).unwrap())
-                .unwrap()
-                .to_rfc3339(),
-        ));
-    }
+pub fn start_of_day(date: NaiveDate) -> DateTime<Tz> {
+    Tokyo
+        .from_local_datetime(&date.and_time(chrono::NaiveTime::MIN))
+        .single()
+        .expect("midnight should exist in Asia/Tokyo")
+}
+
+// interactiveなら、初回はブラウザでの認証を始めて待つ
+fn new_fetcher(interactive: bool) -> Result<Fetcher> {
+    let var = |name: &str| {
+        env::var(name).map_err(|_| FetchError::Auth(format!("{} is not defined in env", name)))
+    };
+    Fetcher::new(
+        var("GOOGLE_CLIENT_ID")?,
+        var("GOOGLE_CLIENT_SECRET")?,
+        calendar_list(),
+        interactive,
+    )
+}
+
+// 起動時のトークンのリフレッシュで通信できなかったときなどは、その原因の終了コードにする
+fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .downcast_ref::<FetchError>()
+        .map_or(1, FetchError::exit_code)
+}
+
+// 予定をテキストで書き出して終了コードを返す。失敗の原因はexit codeで区別できる
+fn run_agenda(config: &Config, format: AgendaFormat, date: Option<NaiveDate>) -> i32 {
+    let now = Utc::now().with_timezone(&Tokyo);
+    let date = date.map(start_of_day).unwrap_or(now);
 
-    fn fetch_date_events(self: &mut Self, date: DateTime<Tz>) -> Result<()> {
-        self.fetched_time = date;
-        let client = Client::new();
-
-        let (time_min, time_max) = App::get_utc_date_range_string(date)?;
-
-        println!("Fetching events for date: {}", date);
-        println!("Time Min (UTC): {}", time_min);
-        println!("Time Max (UTC): {}", time_max);
-
-        self.events = Some(
-            self.calendar_list
-                .iter()
-                .flat_map(|calendar| -> Vec<EventModel> {
-                    let url = Url::parse(
-                        format!(
-                            "https://www.googleapis.com/calendar/v3/calendars/{}/events",
-                            calendar.id()
-                        )
-                        .as_str(),
-                    )
-                    .expect("URL should be valid");
-
-                    use reqwest::StatusCode;
-
-                    let mut response = client
-                        .get(url.clone())
-                        .query(&[("timeMin", time_min.as_str())])
-                        .query(&[("timeMax", time_max.as_str())])
-                        .query(&[("orderBy", "startTime")])
-                        .query(&[("singleEvents", "true")])
-                        .query(&[("access_type", "offline")])
-                        .query(&[("prompt", "consent")])
-                        .bearer_auth(self.token.access_token.clone())
-                        .send()
-                        .expect("Request should be sent");
-
-                    if response.status() == StatusCode::UNAUTHORIZED {
-                        eprintln!("Access token expired or invalid. Attempting to refresh token...");
-                        match self.token.refresh() {
-                            Ok(_) => {
-                                eprintln!("Token refresh succeeded. Retrying request...");
-                                response = client
-                                    .get(url)
-                                    .query(&[("timeMin", time_min.as_str())])
-                                    .query(&[("timeMax", time_max.as_str())])
-                                    .query(&[("orderBy", "startTime")])
-                                    .query(&[("singleEvents", "true")])
-                                    .query(&[("access_type", "offline")])
-                                    .query(&[("prompt", "consent")])
-                                    .bearer_auth(self.token.access_token.clone())
-                                    .send()
-                                    .expect("Request should be sent (after refresh)");
-                                if !response.status().is_success() {
-                                    eprintln!("Request failed after token refresh: {:?}", response.status());
-                                    panic!(
-                                        "Request failed with text after refresh: {}",
-                                        response.text().unwrap_or_default()
-                                    );
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("Token refresh failed: {:?}", e);
-                                panic!("Token refresh failed: {:?}", e);
-                            }
-                        }
-                    } else if !response.status().is_success() {
-                        eprintln!("Error: {:?}", response.status());
-                        panic!(
-                            "Request failed with text: {}",
-                            response.text().unwrap_or_default()
-                        );
-                    }
-
-                    let response_text = response.text().expect("Response should be text");
-
-                    let response_data = serde_json::from_str::<google_calendar3::api::Events>(
-                        response_text.as_str(),
-                    )
-                    .expect("Response should be deserialized");
-                    response_data
-                        .items
-                        .unwrap_or_default()
-                        .iter()
-                        .map(|event| EventModel::new(event.clone(), calendar.clone()))
-                        .collect()
-                })
-                .collect::<Vec<EventModel>>(),
-        );
-        Ok(())
+    // スクリプトから使うので、認証が済んでいなければブラウザでの認証を始めずに終わる
+    let mut fetcher = match new_fetcher(false) {
+        Ok(fetcher) => fetcher,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            return exit_code(&e);
+        }
+    };
+
+    let events = match fetcher.fetch_date_events(date, 1) {
+        Ok(mut events) => {
+            events.retain(|event| config.filter.shows(event));
+            rules::apply(&config.rules, events)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return e.exit_code();
+        }
+    };
+
+    match agenda::render(&events, date.date_naive(), format) {
+        Ok(output) => {
+            print!("{}", output);
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            1
+        }
     }
+}
 
-    fn render_ui(
-        terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-        events: Vec<EventView>,
-        now: DateTime<Tz>,
-    ) -> Result<()> {
-        terminal.clear()?;
-        terminal.draw(|terminal_window| {
-            // render events
-            let height_unit: u16 = terminal_window.area().height / 48;
-            for event in events {
-                let size = Rect {
-                    x: 1 + terminal_window.area().x,
-                    y: event.start * height_unit,
-                    width: terminal_window.area().width - 1,
-                    height: event.height * height_unit,
-                };
-
-                terminal_window.render_widget(
-                    Paragraph::new(event.title).block(
-                        Block::default()
-                            .borders(Borders::NONE)
-                            .style(Style::default().bg(event.color)),
-                    ),
-                    size,
-                );
-            }
-            // render now line
-            let now_height = EventView::date_time_to_height(now, &Tokyo);
-            let size = Rect {
-                x: 0,
-                y: now_height,
-                width: 1,
-                height: 1,
-            };
-            terminal_window.render_widget(
-                Paragraph::new(">").block(
-                    Block::default()
-                        .borders(Borders::NONE)
-                        .style(Style::default().bg(ratatui::style::Color::Yellow)),
-                ),
-                size,
-            );
-        })?;
-        Ok(())
+// TUIと同じ表示を1枚の画像にして終了コードを返す
+// cronなどから使うので、--output と同じくブラウザでの認証は始めない
+fn run_render(
+    config: Config,
+    cli: &Cli,
+    path: &Path,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> i32 {
+    let now = Utc::now().with_timezone(&Tokyo);
+    let date = cli.date.map(start_of_day).unwrap_or(now);
+    render(
+        config,
+        new_fetcher(false),
+        date,
+        cli.days,
+        path,
+        width,
+        height,
+    )
+}
+
+fn render(
+    config: Config,
+    fetcher: Result<impl FetchEvents + 'static>,
+    date: DateTime<Tz>,
+    days: u32,
+    path: &Path,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> i32 {
+    let mut fetcher = match fetcher {
+        Ok(fetcher) => fetcher,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            return exit_code(&e);
+        }
+    };
+    let events = match fetcher.fetch_date_events(date, days) {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return e.exit_code();
+        }
+    };
+
+    let width = width.unwrap_or(config.pixel.width);
+    let height = height.unwrap_or(config.pixel.height);
+    let font = config.pixel.font.clone();
+    let (mut app, _) = App::new(config, fetcher, date, days);
+    app.set_events(events);
+    match image::write(&app, path, width, height, &font) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            1
+        }
     }
 }
 
+fn run_tui<B: Backend + Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    receiver: UnboundedReceiver<AppEvent>,
+    cli: &Cli,
+) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(async {
+        // 初回の予定取得
+        match cli.date {
+            Some(date) => app.show_date(date),
+            None => app.fetch_date_events(Utc::now().with_timezone(&Tokyo)),
+        }
+
+        // エラーハンドリング付きのメインループ
+        app::run_app(
+            terminal,
+            app,
+            receiver,
+            Duration::from_secs(cli.idle_timeout),
+            cli.heartbeat.as_deref(),
+        )
+        .await
+    });
+    // 取得中の通信が終わるのを待たずに終了する
+    runtime.shutdown_background();
+    result
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
     // 環境変数の読み込み
     dotenv::dotenv().ok();
 
-    // ターミナルの初期化
-    crossterm::terminal::enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    crossterm::execute!(
-        stdout,
-        crossterm::terminal::EnterAlternateScreen,
-        crossterm::event::EnableMouseCapture
-    )?;
+    match &cli.command {
+        Some(Commands::Supervise {
+            hang_minutes,
+            heartbeat,
+        }) => {
+            supervisor::supervise(
+                cli.child_args(),
+                heartbeat,
+                Duration::from_secs(hang_minutes * 60),
+            )?;
+            return Ok(());
+        }
+        Some(Commands::SystemdUnit { tty }) => {
+            let user = env::var("USER").unwrap_or_else(|_| "pi".to_string());
+            print!(
+                "{}",
+                supervisor::systemd_unit(&env::current_exe()?, &env::current_dir()?, &user, tty)
+            );
+            return Ok(());
+        }
+        Some(Commands::Render { .. }) | None => {}
+    }
 
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let config = Config::load(&cli.config)?;
 
-    // ctrlc ハンドラーの設定
-    ctrlc::set_handler(move || {
-        println!("終了処理中...");
-        if let Err(e) = crossterm::terminal::disable_raw_mode() {
-            eprintln!("終了処理中にエラーが発生しました: {:?}", e);
-        }
-        std::process::exit(0);
-    })?;
+    if let Some(format) = cli.output {
+        std::process::exit(run_agenda(&config, format, cli.date));
+    }
+
+    if let Some(Commands::Render {
+        path,
+        width,
+        height,
+    }) = &cli.command
+    {
+        std::process::exit(run_render(config, &cli, path, *width, *height));
+    }
+
+    // 端末を使わずに描く場合の描画先。フォントが無いなどの失敗は画面を切り替える前に出す
+    let output = match (&cli.framebuffer, &cli.epaper, &cli.png) {
+        (Some(device), _, _) => Some(Output::Framebuffer(Framebuffer::open(device)?)),
+        (None, Some(device), _) => Some(Output::Epaper(Epaper::open(device, &config.epaper)?)),
+        (None, None, Some(path)) => Some(Output::Png {
+            path: path.clone(),
+            width: config.pixel.width,
+            height: config.pixel.height,
+        }),
+        (None, None, None) => None,
+    };
+    let pixel_backend = match output {
+        Some(output) => Some(PixelBackend::new(
+            BdfFont::load(&config.pixel.font)?,
+            output,
+        )),
+        None => None,
+    };
 
     // アプリケーションの初期化
-    let mut app = App::new(
-        env::var("GOOGLE_CLIENT_ID").expect("GOOGLE_CLIENT_ID is not defined in env"),
-        env::var("GOOGLE_CLIENT_SECRET").expect("GOOGLE_CLIENT_SECRET is not defined in env"),
-        Utc::now().with_timezone(&Tokyo),
-        vec![Calendar::Primary, Calendar::Private, Calendar::University],
-    )?;
+    // 初回の認証ではURLを表示するので、TUIに切り替える前に行う
+    let fetcher = new_fetcher(true)?;
 
-    // 初回の予定取得と表示
-    (app.fetch_date_events(Utc::now().with_timezone(&Tokyo))?);
+    // 同じ表示と取得の状況をHTTPでも見せる
+    let dashboard = match &config.dashboard.listen {
+        Some(address) => {
+            let dashboard = Dashboard::new(fetcher.metrics());
+            dashboard.serve(address)?;
+            Some(dashboard)
+        }
+        None => None,
+    };
 
-    {
-        App::render_ui(
-            &mut terminal,
-            app.events
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("Events are not fetched"))?
-                .iter()
-                .map(|event| EventView::from_event(event.clone()))
-                .filter_map(|event| event.ok())
-                .collect(),
-            Utc::now().with_timezone(&Tokyo),
-        )?;
+    let countdown_minutes = config.epaper.countdown_minutes;
+    let (mut app, receiver) = App::new(config, fetcher, Utc::now().with_timezone(&Tokyo), cli.days);
+    if let Some(dashboard) = dashboard {
+        app.set_dashboard(dashboard);
+    }
+    if cli.epaper.is_some() {
+        app.set_countdown_minutes(countdown_minutes);
+    }
+
+    // ctrlc ハンドラーの設定。終了処理はメインループから抜けたあとにまとめて行う
+    let quit_sender = app.sender();
+    ctrlc::set_handler(move || {
+        let _ = quit_sender.send(AppEvent::Quit);
+    })?;
+
+    // ターミナルの初期化。エラーやpanicで抜けてもguardが端末を元に戻す
+    match pixel_backend {
+        Some(backend) => {
+            let mut terminal = TerminalGuard::pixel(backend)?;
+            run_tui(&mut terminal, &mut app, receiver, &cli)?;
+        }
+        None => {
+            let mut terminal = TerminalGuard::new()?;
+            run_tui(&mut terminal, &mut app, receiver, &cli)?;
+        }
     }
 
-    // エラーハンドリング付きのメインループ
-    run_app(&mut terminal, &mut app)?;
-
-    // エラーを返す
-    crossterm::terminal::disable_raw_mode()?;
-    crossterm::execute!(
-        terminal.backend_mut(),
-        crossterm::terminal::LeaveAlternateScreen,
-        crossterm::event::DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
     Ok(())
 }
 
-fn run_app(
-    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-    app: &mut App,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut last_check = std::time::Instant::now();
-    let check_interval = Duration::from_secs(60); // 1分ごとにチェック
-
-    loop {
-        // キー入力をポーリング（タイムアウト付き）
-        if crossterm::event::poll(Duration::from_millis(100))? {
-            if let crossterm::event::Event::Key(key) = crossterm::event::read()? {
-                if key.kind == crossterm::event::KeyEventKind::Press
-                    && key.code == crossterm::event::KeyCode::Char('q')
-                {
-                    return Ok(());
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventModel;
+
+    // 認証が済んでいないときに呼ばれたら失敗にする
+    struct Unreachable;
 
-        // 現在時刻を確認
-        let now = std::time::Instant::now();
-        if now.duration_since(last_check) >= check_interval {
-            let now_date = Utc::now().with_timezone(&Tokyo);
-
-            //30分ごとにUIを更新
-            if now_date.minute() == 0 || now_date.minute() == 30 {
-                App::render_ui(
-                    terminal,
-                    app.events
-                        .as_ref()
-                        .ok_or_else(|| anyhow::anyhow!("Events are not fetched"))?
-                        .iter()
-                        .map(|event| EventView::from_event(event.clone()))
-                        .filter_map(|event| event.ok())
-                        .collect(),
-                    Utc::now().with_timezone(&Tokyo),
-                )?;
-            }
-
-            // 日付が変わった場合はeventを再取得
-            if now_date.day() != app.fetched_time.day() {
-                app.fetch_date_events(now_date)?;
-            }
-            last_check = now;
+    impl FetchEvents for Unreachable {
+        fn fetch_date_events(
+            &mut self,
+            _date: DateTime<Tz>,
+            _days: u32,
+        ) -> Result<Vec<EventModel>, FetchError> {
+            panic!("should not fetch without tokens");
         }
     }
+
+    #[test]
+    fn test_render_without_tokens() {
+        let path = env::temp_dir().join(format!("render-test-{}.svg", std::process::id()));
+        let fetcher: Result<Unreachable> = Err(FetchError::Auth(
+            "tokens.json is not found; run without --output once to authorize".to_string(),
+        )
+        .into());
+        let date = start_of_day(NaiveDate::from_ymd_opt(2023, 10, 2).unwrap());
+        let code = render(Config::default(), fetcher, date, 1, &path, None, None);
+        assert_eq!(code, 2);
+        assert!(!path.exists());
+    }
 }
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::calendar::Calendar;
+use crate::error::FetchError;
+
+// 取得にかかった秒数のヒストグラムの区切り
+const BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+// 予定の取得の状況。Fetcherが書き、[dashboard] のHTTPサーバーがPrometheusの形式で返す
+// 複数の表示器のうち、どれが古い予定のまま止まっているかを見分けるのに使う
+#[derive(Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    // カレンダー名ごとの最後に取得できた時刻 (UNIX時間)
+    last_success: BTreeMap<&'static str, i64>,
+    // BUCKETS の区切りごとの件数。最後の要素は +Inf
+    buckets: [u64; BUCKETS.len() + 1],
+    duration_sum: f64,
+    duration_count: u64,
+    // ラベル (type="http",status="503" など) ごとのエラーの件数
+    errors: BTreeMap<String, u64>,
+    // 成功したリフレッシュの回数。失敗はerrorsの type="auth" などに入る
+    token_refreshes: u64,
+}
+
+impl Metrics {
+    // カレンダー1つ分の取得の結果
+    pub fn record_fetch<T>(
+        &self,
+        calendar: &Calendar,
+        duration: Duration,
+        result: &Result<T, FetchError>,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        let seconds = duration.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(BUCKETS.len());
+        inner.buckets[bucket] += 1;
+        inner.duration_sum += seconds;
+        inner.duration_count += 1;
+        match result {
+            Ok(_) => {
+                inner
+                    .last_success
+                    .insert(calendar.name(), Utc::now().timestamp());
+            }
+            Err(e) => *inner.errors.entry(error_labels(e)).or_default() += 1,
+        }
+    }
+
+    // カレンダーの予定以外の取得 (予定の色の一覧など) の失敗
+    pub fn record_error(&self, error: &FetchError) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .errors
+            .entry(error_labels(error))
+            .or_default() += 1;
+    }
+
+    pub fn count_token_refresh(&self) {
+        self.inner.lock().unwrap().token_refreshes += 1;
+    }
+
+    // Prometheusのテキスト形式。表示中の予定の数は描画した内容から数えて渡す
+    pub fn render(&self, events_displayed: usize) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut text = String::new();
+
+        header(
+            &mut text,
+            "last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful fetch per calendar.",
+        );
+        for (calendar, timestamp) in &inner.last_success {
+            let _ = writeln!(
+                text,
+                "today_calendar_last_success_timestamp_seconds{{calendar=\"{}\"}} {}",
+                calendar, timestamp
+            );
+        }
+
+        header(
+            &mut text,
+            "fetch_duration_seconds",
+            "histogram",
+            "Time taken to fetch the events of one calendar.",
+        );
+        let mut cumulative = 0;
+        for (i, count) in inner.buckets.iter().enumerate() {
+            cumulative += count;
+            let le = BUCKETS
+                .get(i)
+                .map_or("+Inf".to_string(), |le| le.to_string());
+            let _ = writeln!(
+                text,
+                "today_calendar_fetch_duration_seconds_bucket{{le=\"{}\"}} {}",
+                le, cumulative
+            );
+        }
+        let _ = writeln!(
+            text,
+            "today_calendar_fetch_duration_seconds_sum {}",
+            inner.duration_sum
+        );
+        let _ = writeln!(
+            text,
+            "today_calendar_fetch_duration_seconds_count {}",
+            inner.duration_count
+        );
+
+        header(
+            &mut text,
+            "fetch_errors_total",
+            "counter",
+            "Failed fetches by error type.",
+        );
+        // 一度も起きていない種類も0として出しておくと、増えたことをアラートにしやすい
+        let mut errors = inner.errors.clone();
+        for kind in ["auth", "network", "parse"] {
+            errors.entry(format!("type=\"{}\"", kind)).or_default();
+        }
+        for (labels, count) in &errors {
+            let _ = writeln!(
+                text,
+                "today_calendar_fetch_errors_total{{{}}} {}",
+                labels, count
+            );
+        }
+
+        header(
+            &mut text,
+            "token_refreshes_total",
+            "counter",
+            "Successful access token refreshes, including the one at startup.",
+        );
+        let _ = writeln!(
+            text,
+            "today_calendar_token_refreshes_total {}",
+            inner.token_refreshes
+        );
+
+        header(
+            &mut text,
+            "events_displayed",
+            "gauge",
+            "Number of events currently on the screen.",
+        );
+        let _ = writeln!(text, "today_calendar_events_displayed {}", events_displayed);
+        text
+    }
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP today_calendar_{} {}", name, help);
+    let _ = writeln!(text, "# TYPE today_calendar_{} {}", name, kind);
+}
+
+fn error_labels(error: &FetchError) -> String {
+    match error {
+        FetchError::Auth(_) => "type=\"auth\"".to_string(),
+        FetchError::Network(_) => "type=\"network\"".to_string(),
+        FetchError::Http { status, .. } => {
+            format!("status=\"{}\",type=\"http\"", status.as_u16())
+        }
+        FetchError::Parse(_) => "type=\"parse\"".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.record_fetch(
+            &Calendar::Primary,
+            Duration::from_millis(300),
+            &Ok::<_, FetchError>(()),
+        );
+        metrics.record_fetch::<()>(
+            &Calendar::University,
+            Duration::from_secs(60),
+            &Err(FetchError::Http {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                body: String::new(),
+            }),
+        );
+        metrics.count_token_refresh();
+
+        let text = metrics.render(3);
+        assert!(
+            text.contains("today_calendar_last_success_timestamp_seconds{calendar=\"Primary\"} ")
+        );
+        assert!(!text.contains("calendar=\"University\""));
+        assert!(text.contains("today_calendar_fetch_duration_seconds_bucket{le=\"0.25\"} 0\n"));
+        assert!(text.contains("today_calendar_fetch_duration_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("today_calendar_fetch_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("today_calendar_fetch_duration_seconds_count 2\n"));
+        assert!(
+            text.contains("today_calendar_fetch_errors_total{status=\"503\",type=\"http\"} 1\n")
+        );
+        assert!(text.contains("today_calendar_fetch_errors_total{type=\"auth\"} 0\n"));
+        assert!(text.contains("today_calendar_token_refreshes_total 1\n"));
+        assert!(text.contains("today_calendar_events_displayed 3\n"));
+    }
+
+    #[test]
+    fn test_refresh_failure() {
+        // リフレッシュの失敗は取得の失敗としてauthに数え、リフレッシュの回数には入れない
+        let metrics = Metrics::default();
+        metrics.record_fetch::<()>(
+            &Calendar::Primary,
+            Duration::from_millis(300),
+            &Err(FetchError::Auth("token refresh failed".to_string())),
+        );
+        let text = metrics.render(0);
+        assert!(text.contains("today_calendar_fetch_errors_total{type=\"auth\"} 1\n"));
+        assert!(text.contains("today_calendar_token_refreshes_total 0\n"));
+
+        // 色の一覧の取得の失敗はヒストグラムに入れずにエラーとして数える
+        metrics.record_error(&FetchError::Auth("token refresh failed".to_string()));
+        let text = metrics.render(0);
+        assert!(text.contains("today_calendar_fetch_errors_total{type=\"auth\"} 2\n"));
+        assert!(text.contains("today_calendar_fetch_duration_seconds_count 1\n"));
+    }
+}
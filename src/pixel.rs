@@ -0,0 +1,451 @@
+use std::fs::OpenOptions;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use ratatui::backend::{Backend, WindowSize};
+use ratatui::buffer::{Buffer, Cell};
+use ratatui::layout::{Position, Rect, Size};
+use ratatui::style::Modifier;
+use unicode_width::UnicodeWidthStr;
+
+use crate::bdf::BdfFont;
+use crate::epaper::Epaper;
+use crate::png;
+use crate::theme;
+
+pub type Rgb = (u8, u8, u8);
+
+// 色が Reset のときに使う色。コンソールと同じ黒地に明るい灰色
+const DEFAULT_FOREGROUND: Rgb = (0xe5, 0xe5, 0xe5);
+const DEFAULT_BACKGROUND: Rgb = (0x00, 0x00, 0x00);
+
+// RGBのピクセル列
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Rgb>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: vec![DEFAULT_BACKGROUND; (width * height) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Rgb {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    fn set(&mut self, x: i32, y: i32, color: Rgb) {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            self.pixels[(y as u32 * self.width + x as u32) as usize] = color;
+        }
+    }
+
+    pub fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, color: Rgb) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                self.pixels[(py * self.width + px) as usize] = color;
+            }
+        }
+    }
+
+    pub fn rgb_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&(r, g, b)| [r, g, b])
+            .collect()
+    }
+
+    // ratatuiのバッファをフォントで描く。端末と同じセルの配置をそのままピクセルに直す
+    pub fn render(buffer: &Buffer, font: &BdfFont, width: u32, height: u32) -> Self {
+        let mut canvas = Canvas::new(width, height);
+        let (cell_width, cell_height) = (font.cell_width, font.cell_height);
+        let cells = || {
+            buffer.content.iter().enumerate().map(|(i, cell)| {
+                let (x, y) = buffer.pos_of(i);
+                (x as u32 * cell_width, y as u32 * cell_height, cell)
+            })
+        };
+
+        // 全角文字は右隣のセルにはみ出すので、背景を全部塗ってから文字を描く
+        for (x, y, cell) in cells() {
+            let (_, background) = colors(cell);
+            canvas.fill(x, y, cell_width, cell_height, background);
+        }
+        for (x, y, cell) in cells() {
+            if cell.skip {
+                continue;
+            }
+            let (foreground, _) = colors(cell);
+            let symbol = cell.symbol();
+            let cells = symbol.width().max(1) as u32;
+            for c in symbol.chars().filter(|c| !c.is_whitespace()) {
+                for (px, py) in font.pixels(c, cells) {
+                    canvas.set(x as i32 + px, y as i32 + py, foreground);
+                    if cell.modifier.contains(Modifier::BOLD) {
+                        canvas.set(x as i32 + px + 1, y as i32 + py, foreground);
+                    }
+                }
+            }
+            let width = cell_width * cells;
+            if cell.modifier.contains(Modifier::UNDERLINED) {
+                canvas.fill(x, y + cell_height - 1, width, 1, foreground);
+            }
+            if cell.modifier.contains(Modifier::CROSSED_OUT) {
+                canvas.fill(x, y + cell_height / 2, width, 1, foreground);
+            }
+        }
+        canvas
+    }
+}
+
+// セルの文字色と背景色。反転や薄い表示もここで色に直す
+pub fn colors(cell: &Cell) -> (Rgb, Rgb) {
+    let mut foreground = theme::rgb(cell.fg).unwrap_or(DEFAULT_FOREGROUND);
+    let mut background = theme::rgb(cell.bg).unwrap_or(DEFAULT_BACKGROUND);
+    if cell.modifier.contains(Modifier::REVERSED) {
+        std::mem::swap(&mut foreground, &mut background);
+    }
+    if cell.modifier.contains(Modifier::DIM) {
+        let mix = |f: u8, b: u8| ((f as u16 + b as u16) / 2) as u8;
+        foreground = (
+            mix(foreground.0, background.0),
+            mix(foreground.1, background.1),
+            mix(foreground.2, background.2),
+        );
+    }
+    (foreground, background)
+}
+
+// 全角文字の右隣のセルはratatuiの差分で送られてこないので、最初の色 (Reset) のまま残る
+// そのまま描くと文字ごとに黒い穴が空くので、全角文字のセルと同じ色にしておく
+pub fn fill_wide_cells(buffer: &mut Buffer) {
+    let width = buffer.area.width as usize;
+    let mut i = 0;
+    while i < buffer.content.len() {
+        let cells = buffer.content[i].symbol().width().max(1);
+        let wide = buffer.content[i].clone();
+        // 行の右端を越えて次の行を塗らないようにする
+        for j in (i + 1)..(i + cells).min((i / width + 1) * width) {
+            let cell = &mut buffer.content[j];
+            cell.fg = wide.fg;
+            cell.bg = wide.bg;
+            cell.modifier = wide.modifier;
+        }
+        i += cells;
+    }
+}
+
+// 描画先
+pub enum Output {
+    Framebuffer(Framebuffer),
+    // SPIでつないだ電子ペーパー。変わったところだけを書き換える
+    Epaper(Epaper),
+    // 描画のたびに上書きするPNGファイル (動作確認用)
+    Png {
+        path: PathBuf,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl Output {
+    fn size(&self) -> (u32, u32) {
+        match self {
+            Output::Framebuffer(framebuffer) => (framebuffer.width, framebuffer.height),
+            Output::Epaper(epaper) => (epaper.width, epaper.height),
+            Output::Png { width, height, .. } => (*width, *height),
+        }
+    }
+
+    fn write(&mut self, canvas: &Canvas) -> io::Result<()> {
+        match self {
+            Output::Framebuffer(framebuffer) => framebuffer.write(canvas),
+            Output::Epaper(epaper) => epaper.write(canvas),
+            Output::Png { path, .. } => std::fs::write(
+                path,
+                png::encode(canvas.width, canvas.height, &canvas.rgb_bytes()),
+            ),
+        }
+    }
+}
+
+// Linuxのフレームバッファ (/dev/fb0 など)。大きさと形式は /sys/class/graphics から読む
+pub struct Framebuffer {
+    device: PathBuf,
+    width: u32,
+    height: u32,
+    bits_per_pixel: u32,
+    // 1行あたりのバイト数
+    stride: u32,
+}
+
+impl Framebuffer {
+    pub fn open(device: &Path) -> Result<Self> {
+        let name = device
+            .file_name()
+            .ok_or_else(|| anyhow!("invalid framebuffer device: {}", device.display()))?;
+        let sysfs = Path::new("/sys/class/graphics").join(name);
+        let read = |attribute: &str| -> Result<String> {
+            let path = sysfs.join(attribute);
+            Ok(std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?
+                .trim()
+                .to_string())
+        };
+
+        let size = read("virtual_size")?;
+        let (width, height) = size
+            .split_once(',')
+            .ok_or_else(|| anyhow!("invalid virtual_size: {}", size))?;
+        let bits_per_pixel = read("bits_per_pixel")?.parse()?;
+        if bits_per_pixel != 16 && bits_per_pixel != 32 {
+            return Err(anyhow!("unsupported bits_per_pixel: {}", bits_per_pixel));
+        }
+        Ok(Framebuffer {
+            device: device.to_path_buf(),
+            width: width.parse()?,
+            height: height.parse()?,
+            bits_per_pixel,
+            stride: read("stride")?.parse()?,
+        })
+    }
+
+    fn write(&self, canvas: &Canvas) -> io::Result<()> {
+        let mut frame = vec![0u8; (self.stride * self.height) as usize];
+        for y in 0..canvas.height.min(self.height) {
+            let row = &mut frame[(y * self.stride) as usize..];
+            for x in 0..canvas.width.min(self.width) {
+                let (r, g, b) = canvas.pixel(x, y);
+                match self.bits_per_pixel {
+                    // RGB565
+                    16 => {
+                        let value =
+                            ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3);
+                        row[(x * 2) as usize..(x * 2 + 2) as usize]
+                            .copy_from_slice(&value.to_le_bytes());
+                    }
+                    // XRGB8888 (メモリ上はB, G, R, X の順)
+                    _ => row[(x * 4) as usize..(x * 4 + 4) as usize].copy_from_slice(&[b, g, r, 0]),
+                }
+            }
+        }
+        let mut device = OpenOptions::new().write(true).open(&self.device)?;
+        device.seek(SeekFrom::Start(0))?;
+        device.write_all(&frame)
+    }
+}
+
+// 端末の代わりにピクセルへ描くratatuiのバックエンド
+// レイアウトは端末と同じく文字のセル単位で計算され、flushのたびに画面全体を描き直す
+pub struct PixelBackend {
+    font: BdfFont,
+    output: Output,
+    buffer: Buffer,
+    cursor: Position,
+}
+
+impl PixelBackend {
+    pub fn new(font: BdfFont, output: Output) -> Self {
+        let (width, height) = output.size();
+        let area = Rect::new(
+            0,
+            0,
+            (width / font.cell_width) as u16,
+            (height / font.cell_height) as u16,
+        );
+        PixelBackend {
+            font,
+            output,
+            buffer: Buffer::empty(area),
+            cursor: Position::ORIGIN,
+        }
+    }
+}
+
+impl Backend for PixelBackend {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        for (x, y, cell) in content {
+            if let Some(target) = self.buffer.cell_mut((x, y)) {
+                *target = cell.clone();
+            }
+        }
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn get_cursor_position(&mut self) -> io::Result<Position> {
+        Ok(self.cursor)
+    }
+
+    fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> io::Result<()> {
+        self.cursor = position.into();
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.buffer.reset();
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<Size> {
+        Ok(self.buffer.area.as_size())
+    }
+
+    fn window_size(&mut self) -> io::Result<WindowSize> {
+        let (width, height) = self.output.size();
+        Ok(WindowSize {
+            columns_rows: self.buffer.area.as_size(),
+            pixels: Size::new(width as u16, height as u16),
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        fill_wide_cells(&mut self.buffer);
+        let (width, height) = self.output.size();
+        let canvas = Canvas::render(&self.buffer, &self.font, width, height);
+        self.output.write(&canvas)
+    }
+}
+
+// ベルなど端末向けに書き込まれる制御文字は捨てる
+impl Write for PixelBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::{Color, Style};
+
+    use super::*;
+    use crate::config::{ThemeConfig, ThemeName};
+    use crate::event::EventPhase;
+    use crate::theme::Theme;
+
+    #[test]
+    fn test_render_cells() {
+        // 4x6 のセルに「A」だけを持つフォント
+        let font = BdfFont::parse(
+            "STARTFONT 2.1
+FONTBOUNDINGBOX 4 6 0 -1
+STARTCHAR A
+ENCODING 65
+DWIDTH 4 0
+BBX 3 5 0 0
+BITMAP
+40
+A0
+E0
+A0
+A0
+ENDCHAR
+ENDFONT
+",
+        )
+        .unwrap();
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 2, 1));
+        buffer.set_string(0, 0, "A", Style::default().fg(Color::Red).bg(Color::Blue));
+        buffer.set_string(
+            1,
+            0,
+            "A",
+            Style::default()
+                .fg(Color::Red)
+                .bg(Color::Blue)
+                .add_modifier(Modifier::REVERSED),
+        );
+
+        let canvas = Canvas::render(&buffer, &font, 8, 6);
+        let red = (0xcd, 0x00, 0x00);
+        let blue = (0x00, 0x00, 0xee);
+        assert_eq!(canvas.pixel(1, 0), red);
+        assert_eq!(canvas.pixel(0, 0), blue);
+        // 反転したセルは文字と背景の色が入れ替わる
+        assert_eq!(canvas.pixel(5, 0), blue);
+        assert_eq!(canvas.pixel(4, 0), red);
+    }
+
+    #[test]
+    fn test_mono_past_style() {
+        let font = BdfFont::parse(
+            "STARTFONT 2.1
+FONTBOUNDINGBOX 4 6 0 -1
+ENDFONT
+",
+        )
+        .unwrap();
+        let theme = Theme::from_config(&ThemeConfig {
+            name: ThemeName::Mono,
+            ..Default::default()
+        });
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 2, 1));
+        buffer.set_style(
+            Rect::new(0, 0, 1, 1),
+            theme.event_style(Color::White, EventPhase::Past),
+        );
+        buffer.set_style(
+            Rect::new(1, 0, 1, 1),
+            theme.event_style(Color::White, EventPhase::Future),
+        );
+
+        // 終わった予定は白黒の画面でも線が引かれて見分けられる
+        let canvas = Canvas::render(&buffer, &font, 8, 6);
+        assert_ne!(canvas.pixel(1, 3), canvas.pixel(5, 3));
+    }
+
+    #[test]
+    fn test_wide_character_background() {
+        let font = BdfFont::parse(
+            "STARTFONT 2.1
+FONTBOUNDINGBOX 4 6 0 -1
+ENDFONT
+",
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("pixel-test-{}.png", std::process::id()));
+        let output = Output::Png {
+            path: path.clone(),
+            width: 16,
+            height: 6,
+        };
+        let mut terminal = ratatui::Terminal::new(PixelBackend::new(font, output)).unwrap();
+        terminal
+            .draw(|frame| {
+                frame.render_widget(
+                    ratatui::widgets::Paragraph::new("会議")
+                        .style(Style::default().bg(Color::Blue)),
+                    frame.area(),
+                );
+            })
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // 全角文字の右隣のセルも予定の枠と同じ背景になる
+        let buffer = &terminal.backend().buffer;
+        assert_eq!(buffer[(1, 0)].bg, Color::Blue);
+        assert_eq!(buffer[(3, 0)].bg, Color::Blue);
+        let canvas = Canvas::render(buffer, &terminal.backend().font, 16, 6);
+        assert_eq!(canvas.pixel(6, 5), (0x00, 0x00, 0xee));
+    }
+}
@@ -0,0 +1,89 @@
+// 24bitカラーのPNGを書き出す。描画結果の確認用なので圧縮はせず、deflateの無圧縮ブロックで格納する
+pub fn encode(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    assert_eq!(rgb.len(), (width * height * 3) as usize);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = Vec::new();
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // ビット深度8、カラータイプ2 (RGB)、圧縮・フィルタ・インターレースは既定
+    header.extend([8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // 各行の先頭にフィルタの種類 (0: なし) を付ける
+    let row_bytes = (width * 3) as usize;
+    let mut raw = Vec::with_capacity((row_bytes + 1) * height as usize);
+    for row in rgb.chunks(row_bytes.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        zlib.push(last as u8);
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(data).to_be_bytes());
+    zlib
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let png = encode(2, 1, &[255, 0, 0, 0, 0, 255]);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // IENDのCRCはどのPNGでも同じ
+        assert_eq!(&png[png.len() - 8..], b"IEND\xae\x42\x60\x82");
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+}
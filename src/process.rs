@@ -0,0 +1,21 @@
+use std::process::{Command, Stdio};
+
+// 設定で指定されたコマンドを sh -c で実行するためのCommand
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+// 終了を待たずに実行する。画面を崩さないよう入出力は捨て、ゾンビを残さないよう別スレッドで待つ
+// 失敗しても表示には影響しないので無視する
+pub fn spawn_detached(command: &mut Command) {
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Ok(mut child) = child {
+        std::thread::spawn(move || child.wait());
+    }
+}
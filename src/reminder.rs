@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, TimeDelta};
+use chrono_tz::Tz;
+
+use crate::event::{EventModel, EventTime};
+use crate::process;
+
+// 開始前に知らせる予定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reminder {
+    pub title: String,
+    pub start: DateTime<Tz>,
+    pub location: Option<String>,
+}
+
+impl Reminder {
+    pub fn message(&self, now: DateTime<Tz>) -> String {
+        let minutes = (self.start - now).num_minutes().max(0);
+        format!(
+            "{} {} (あと{}分)",
+            self.start.format("%H:%M"),
+            self.title,
+            minutes
+        )
+    }
+
+    // 設定されたコマンドを実行する。予定の情報は環境変数で渡す
+    pub fn run_command(&self, command: &str) {
+        process::spawn_detached(
+            process::shell(command)
+                .env("EVENT_TITLE", &self.title)
+                .env("EVENT_START", self.start.format("%H:%M").to_string())
+                .env(
+                    "EVENT_LOCATION",
+                    self.location.as_deref().unwrap_or_default(),
+                ),
+        );
+    }
+}
+
+// 通知済みのリマインダーを覚えておき、再取得で同じ予定が来ても1回しか知らせない
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    // (予定のID, 開始時刻, 何分前の通知か)
+    fired: HashSet<(String, DateTime<Tz>, i64)>,
+}
+
+impl Scheduler {
+    // nowの時点で通知すべき予定を返す。lead_minutesは予定ごとに開始の何分前に知らせるか
+    // 毎分のチェックで取りこぼさないよう、開始時刻ちょうど (0分前) の通知は開始後2分まで受け付ける
+    pub fn due<'a>(
+        &mut self,
+        events: &'a [EventModel],
+        lead_minutes: impl Fn(&EventModel) -> Vec<i64>,
+        now: DateTime<Tz>,
+    ) -> Vec<&'a EventModel> {
+        // 終わった予定の記録は要らないので、溜まり続けないよう捨てる
+        self.fired
+            .retain(|(_, start, _)| *start > now - TimeDelta::days(1));
+
+        let mut due = Vec::new();
+        for event in events {
+            let Some(EventTime::DateTime(start)) = event.start() else {
+                continue;
+            };
+            let id = event.data().id.clone().unwrap_or_else(|| event.title());
+            let mut fired = false;
+            for minutes in lead_minutes(event) {
+                let fire_at = start - TimeDelta::minutes(minutes);
+                let deadline = start.max(fire_at + TimeDelta::minutes(2));
+                if fire_at <= now
+                    && now < deadline
+                    && self.fired.insert((id.clone(), start, minutes))
+                {
+                    fired = true;
+                }
+            }
+            if fired {
+                due.push(event);
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+    use google_calendar3::api::{Event, EventDateTime, EventReminder, EventReminders};
+
+    use super::*;
+    use crate::calendar::Calendar;
+
+    #[test]
+    fn test_due_fires_once() {
+        let at = |h, m| Tokyo.with_ymd_and_hms(2023, 10, 1, h, m, 0).unwrap();
+        let event = |id: &str, reminders| {
+            EventModel::new(
+                Event {
+                    id: Some(id.to_string()),
+                    start: Some(EventDateTime {
+                        date_time: Some(at(10, 0).to_utc()),
+                        ..Default::default()
+                    }),
+                    reminders,
+                    ..Default::default()
+                },
+                Calendar::Primary,
+            )
+        };
+        let events = vec![
+            // 既定の10分前
+            event("a", None),
+            // 予定ごとに30分前と5分前を設定
+            event(
+                "b",
+                Some(EventReminders {
+                    overrides: Some(
+                        [30, 5]
+                            .map(|minutes| EventReminder {
+                                method: Some("popup".to_string()),
+                                minutes: Some(minutes),
+                            })
+                            .to_vec(),
+                    ),
+                    use_default: Some(false),
+                }),
+            ),
+        ];
+        let ids = |due: Vec<&EventModel>| -> Vec<String> {
+            due.iter()
+                .map(|event| event.data().id.clone().unwrap())
+                .collect()
+        };
+
+        let lead = |event: &EventModel| event.reminder_minutes(10);
+        let mut scheduler = Scheduler::default();
+        assert_eq!(
+            ids(scheduler.due(&events, lead, at(9, 0))),
+            Vec::<String>::new()
+        );
+        assert_eq!(ids(scheduler.due(&events, lead, at(9, 30))), vec!["b"]);
+        // 再取得して同じ予定が来ても2回目は知らせない
+        assert_eq!(
+            ids(scheduler.due(&events, lead, at(9, 31))),
+            Vec::<String>::new()
+        );
+        assert_eq!(ids(scheduler.due(&events, lead, at(9, 50))), vec!["a"]);
+        assert_eq!(ids(scheduler.due(&events, lead, at(9, 55))), vec!["b"]);
+        assert_eq!(
+            ids(scheduler.due(&events, lead, at(10, 0))),
+            Vec::<String>::new()
+        );
+        // 開始時刻ちょうどの通知は開始直後のチェックでも拾う
+        let at_start = |_: &EventModel| vec![0];
+        let mut scheduler = Scheduler::default();
+        assert_eq!(
+            ids(scheduler.due(&events, at_start, at(9, 59))),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(scheduler.due(&events, at_start, at(10, 1))),
+            vec!["a", "b"]
+        );
+    }
+}
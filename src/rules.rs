@@ -0,0 +1,221 @@
+use chrono::NaiveTime;
+use ratatui::style::Color;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+use crate::config::{deserialize_color, deserialize_time, TimeRange};
+use crate::event::{EventModel, EventTime};
+
+// config.toml の [[rules]]。条件をすべて満たした予定に対して、隠す・名前を変える・色を変える・タグを付ける
+// 条件は正規表現で、大文字小文字を区別しないときは "(?i)interview" のように書く
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    title: Option<Regex>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    description: Option<Regex>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    location: Option<Regex>,
+    // カレンダー名 (Primary, Private, University)
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    calendar: Option<Regex>,
+    // 開始時刻が from 以上 until 未満の予定だけに当てはめる。終日予定は対象外
+    // from が until より遅ければ "22:00"〜"06:00" のように日をまたぐ範囲になる
+    #[serde(default, deserialize_with = "deserialize_time")]
+    from: Option<NaiveTime>,
+    #[serde(default, deserialize_with = "deserialize_time")]
+    until: Option<NaiveTime>,
+
+    #[serde(default)]
+    hide: bool,
+    // titleの正規表現があれば "$1" などでキャプチャを使える
+    rename: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_color")]
+    color: Option<Color>,
+    tag: Option<String>,
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+impl Rule {
+    fn matches(&self, event: &EventModel) -> bool {
+        let text_matches = |regex: &Option<Regex>, text: Option<&str>| match regex {
+            Some(regex) => regex.is_match(text.unwrap_or_default()),
+            None => true,
+        };
+        text_matches(&self.title, Some(&event.title()))
+            && text_matches(&self.description, event.data().description.as_deref())
+            && text_matches(&self.location, event.location())
+            && text_matches(&self.calendar, Some(event.calendar().name()))
+            && self.time_matches(event)
+    }
+
+    fn time_matches(&self, event: &EventModel) -> bool {
+        if self.from.is_none() && self.until.is_none() {
+            return true;
+        }
+        let Some(EventTime::DateTime(start)) = event.start() else {
+            return false;
+        };
+        let time = start.time();
+        match (self.from, self.until) {
+            (Some(from), Some(until)) => TimeRange { from, until }.contains(time),
+            (from, until) => {
+                from.is_none_or(|from| from <= time) && until.is_none_or(|until| time < until)
+            }
+        }
+    }
+
+    fn rewrite(&self, mut event: EventModel) -> EventModel {
+        if let Some(rename) = &self.rename {
+            let title = match &self.title {
+                Some(regex) => regex.replace(&event.title(), rename.as_str()).into_owned(),
+                None => rename.clone(),
+            };
+            event = event.with_title(title);
+        }
+        if let Some(color) = self.color {
+            event = event.with_rule_color(color);
+        }
+        if let Some(tag) = &self.tag {
+            event = event.with_tag(tag.clone());
+        }
+        event
+    }
+}
+
+// 上のルールから順に当てはめる。前のルールで変えたタイトルに後のルールがマッチすることもある
+pub fn apply(rules: &[Rule], events: Vec<EventModel>) -> Vec<EventModel> {
+    events
+        .into_iter()
+        .filter_map(|event| {
+            rules.iter().try_fold(event, |event, rule| {
+                if !rule.matches(&event) {
+                    Some(event)
+                } else if rule.hide {
+                    None
+                } else {
+                    Some(rule.rewrite(event))
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+    use google_calendar3::api::{Event, EventDateTime};
+
+    use super::*;
+    use crate::calendar::Calendar;
+
+    fn event(summary: &str, hour: u32) -> EventModel {
+        EventModel::new(
+            Event {
+                summary: Some(summary.to_string()),
+                start: Some(EventDateTime {
+                    date_time: Some(
+                        Tokyo
+                            .with_ymd_and_hms(2023, 10, 1, hour, 0, 0)
+                            .unwrap()
+                            .to_utc(),
+                    ),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            Calendar::Primary,
+        )
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        #[derive(Deserialize)]
+        struct Rules {
+            rules: Vec<Rule>,
+        }
+        let Rules { rules } = toml::from_str(
+            r#"
+            [[rules]]
+            title = "^Focus time$"
+            hide = true
+
+            [[rules]]
+            title = "(?i)interview with (.+)"
+            rename = "面接: $1"
+            color = "magenta"
+            tag = "採用"
+
+            [[rules]]
+            title = "昼"
+            from = "11:00"
+            until = "14:00"
+            tag = "休憩"
+            "#,
+        )
+        .unwrap();
+
+        let events = apply(
+            &rules,
+            vec![
+                event("Focus time", 9),
+                event("Interview with Sato", 10),
+                event("昼ごはん", 12),
+                event("昼の振り返り", 18),
+            ],
+        );
+        let titles: Vec<(String, &[String])> = events
+            .iter()
+            .map(|event| (event.title(), event.tags()))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                ("面接: Sato".to_string(), &["採用".to_string()][..]),
+                ("昼ごはん".to_string(), &["休憩".to_string()][..]),
+                ("昼の振り返り".to_string(), &[][..]),
+            ]
+        );
+        assert_eq!(events[0].rule_color(), Some(Color::Magenta));
+    }
+
+    #[test]
+    fn test_overnight_time() {
+        let rule: Rule = toml::from_str(
+            r#"
+            from = "22:00"
+            until = "06:00"
+            hide = true
+            "#,
+        )
+        .unwrap();
+        assert!(rule.time_matches(&event("夜勤", 23)));
+        assert!(rule.time_matches(&event("夜勤", 5)));
+        assert!(!rule.time_matches(&event("朝会", 6)));
+        assert!(!rule.time_matches(&event("定例", 12)));
+
+        // 時刻の条件があるルールは終日予定には当てはめない
+        let all_day = EventModel::new(
+            Event {
+                summary: Some("休暇".to_string()),
+                start: Some(EventDateTime {
+                    date: chrono::NaiveDate::from_ymd_opt(2023, 10, 1),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            Calendar::Primary,
+        );
+        assert!(!rule.time_matches(&all_day));
+        assert_eq!(apply(&[rule], vec![all_day]).len(), 1);
+    }
+}
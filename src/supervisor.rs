@@ -0,0 +1,251 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+
+use crate::terminal;
+
+// 描画のたびに現在時刻を書き込む。superviseや外部の監視はこのファイルの更新時刻で固まっていないかを見る
+pub fn write_heartbeat(path: &Path) {
+    // 書けなくても表示は続けたいので無視する
+    let _ = std::fs::write(path, Utc::now().to_rfc3339() + "\n");
+}
+
+// 起動してからこれより早く異常終了したら、設定や認証の誤りで起動できていないとみなす
+const STARTUP_WINDOW: Duration = Duration::from_secs(30);
+// 起動直後の異常終了がこの回数続いたら諦めて、systemdの再起動の制限に任せる
+const MAX_STARTUP_FAILURES: u32 = 5;
+
+// 起動直後の異常終了が続いた回数。しばらく動いてから落ちた場合は数え直す
+#[derive(Debug, Default)]
+struct StartupFailures {
+    count: u32,
+}
+
+impl StartupFailures {
+    // 異常終了を記録し、諦めるべきならtrueを返す
+    fn record(&mut self, ran_for: Duration) -> bool {
+        if ran_for < STARTUP_WINDOW {
+            self.count += 1;
+        } else {
+            self.count = 0;
+        }
+        self.count >= MAX_STARTUP_FAILURES
+    }
+}
+
+// TUIを子プロセスとして起動し、落ちたり描画が止まったりしたら起動し直す
+// 子プロセスが正常に終了した (qで終了した) ときはsuperviseも終了する
+// 起動直後に落ち続けるときはエラーで終了する
+pub fn supervise(
+    child_args: Vec<OsString>,
+    heartbeat: &Path,
+    hang_timeout: Duration,
+) -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_handler = stop.clone();
+    ctrlc::set_handler(move || stop_handler.store(true, Ordering::SeqCst))?;
+
+    let mut failures = StartupFailures::default();
+    loop {
+        let started_at = SystemTime::now();
+        let mut child = Command::new(&exe)
+            .args(&child_args)
+            .arg("--heartbeat")
+            .arg(heartbeat)
+            .spawn()?;
+
+        let status = loop {
+            std::thread::sleep(Duration::from_secs(1));
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if stop.load(Ordering::SeqCst) {
+                terminate(&mut child)?;
+                return Ok(());
+            }
+            if is_hung(heartbeat, started_at, hang_timeout) {
+                terminate(&mut child)?;
+                break None;
+            }
+        };
+
+        // 端末がrawモードのまま残っていることがあるので戻しておく
+        let _ = terminal::restore();
+        match status {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) if failures.record(started_at.elapsed().unwrap_or_default()) => {
+                bail!(
+                    "child failed right after startup {} times in a row: {}",
+                    MAX_STARTUP_FAILURES,
+                    describe(status)
+                );
+            }
+            Some(status) => eprintln!("異常終了したため再起動します: {}", describe(status)),
+            None => eprintln!(
+                "{}分以上描画が止まっているため再起動します",
+                hang_timeout.as_secs() / 60
+            ),
+        }
+        // 起動直後に落ち続ける場合に再起動を繰り返しすぎないよう少し待つ
+        std::thread::sleep(Duration::from_secs(5));
+    }
+}
+
+// 子プロセスが描画を始めてから (heartbeatが書かれてから) 更新が止まっているか
+// 初回の認証でURLを表示して待っている間はheartbeatが無いので、固まったとはみなさない
+fn is_hung(heartbeat: &Path, started_at: SystemTime, hang_timeout: Duration) -> bool {
+    let Ok(modified) = std::fs::metadata(heartbeat).and_then(|metadata| metadata.modified()) else {
+        return false;
+    };
+    if modified < started_at {
+        return false;
+    }
+    modified
+        .elapsed()
+        .is_ok_and(|elapsed| elapsed > hang_timeout)
+}
+
+// SIGTERMで終了を促し、終わらなければ強制終了する
+fn terminate(child: &mut Child) -> Result<()> {
+    let _ = Command::new("kill")
+        .arg("-TERM")
+        .arg(child.id().to_string())
+        .status();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if child.try_wait()?.is_some() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    child.kill()?;
+    child.wait()?;
+    Ok(())
+}
+
+fn describe(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exit code {}", code),
+        None => "signal".to_string(),
+    }
+}
+
+// ExecStartに書くパス。systemdは % を指定子、$ を環境変数として展開するので重ねて書く
+// 空白などを含むときは二重引用符で囲み、中の \ と " はエスケープする
+fn exec_path(path: &Path) -> String {
+    let path = path
+        .display()
+        .to_string()
+        .replace('%', "%%")
+        .replace('$', "$$");
+    if path.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\' || c == '\'') {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        path
+    }
+}
+
+// superviseで起動するsystemdのユニット。config.tomlや.envを相対パスで読むので作業ディレクトリも指定する
+// `q` で終了したときは起動し直さないよう、異常終了したときだけ再起動する
+pub fn systemd_unit(exe: &Path, working_directory: &Path, user: &str, tty: &str) -> String {
+    format!(
+        "[Unit]
+Description=today-google-calendar wall display
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+Type=simple
+User={user}
+WorkingDirectory={working_directory}
+ExecStart={exe} supervise
+StandardInput=tty
+StandardOutput=tty
+StandardError=journal
+TTYPath={tty}
+TTYReset=yes
+TTYVHangup=yes
+Environment=TERM=linux
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=multi-user.target
+",
+        user = user,
+        working_directory = working_directory.display().to_string().replace('%', "%%"),
+        exe = exec_path(exe),
+        tty = tty,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systemd_unit() {
+        let unit = systemd_unit(
+            Path::new("/home/pi/today-google-calendar"),
+            Path::new("/home/pi"),
+            "pi",
+            "/dev/tty1",
+        );
+        assert!(unit.contains("ExecStart=/home/pi/today-google-calendar supervise\n"));
+        assert!(unit.contains("WorkingDirectory=/home/pi\n"));
+        assert!(unit.contains("User=pi\n"));
+        assert!(unit.contains("TTYPath=/dev/tty1\n"));
+        assert!(unit.contains("Restart=on-failure\n"));
+
+        let unit = systemd_unit(
+            Path::new("/home/pi/My Apps/today-google-calendar"),
+            Path::new("/home/pi/My Apps"),
+            "pi",
+            "/dev/tty1",
+        );
+        assert!(unit.contains("ExecStart=\"/home/pi/My Apps/today-google-calendar\" supervise\n"));
+        assert!(unit.contains("WorkingDirectory=/home/pi/My Apps\n"));
+        assert_eq!(
+            exec_path(Path::new("/opt/100%/a\"b")),
+            "\"/opt/100%%/a\\\"b\""
+        );
+    }
+
+    #[test]
+    fn test_startup_failures() {
+        let mut failures = StartupFailures::default();
+        for _ in 1..MAX_STARTUP_FAILURES {
+            assert!(!failures.record(Duration::from_secs(1)));
+        }
+        // しばらく動いてから落ちたときは数え直す
+        assert!(!failures.record(Duration::from_secs(60 * 60)));
+        for _ in 1..MAX_STARTUP_FAILURES {
+            assert!(!failures.record(Duration::from_secs(1)));
+        }
+        assert!(failures.record(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_is_hung() {
+        let path = std::env::temp_dir().join(format!("heartbeat-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let started_at = SystemTime::now() - Duration::from_secs(60);
+        // まだ描画していなければ固まったとはみなさない
+        assert!(!is_hung(&path, started_at, Duration::ZERO));
+
+        write_heartbeat(&path);
+        assert!(!is_hung(&path, started_at, Duration::from_secs(60)));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(is_hung(&path, started_at, Duration::from_millis(10)));
+        // 前回の子プロセスが残したheartbeatは見ない
+        assert!(!is_hung(&path, SystemTime::now(), Duration::ZERO));
+        let _ = std::fs::remove_file(&path);
+    }
+}
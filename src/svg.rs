@@ -0,0 +1,79 @@
+use ratatui::buffer::Buffer;
+use ratatui::style::Modifier;
+
+use crate::pixel::{self, Rgb};
+
+// ratatuiのバッファをSVGにする。セルの配置はPNGやフレームバッファと同じで、文字だけSVGのフォントで描く
+pub fn render(buffer: &Buffer, cell_width: u32, cell_height: u32) -> String {
+    let mut buffer = buffer.clone();
+    pixel::fill_wide_cells(&mut buffer);
+    let area = buffer.area;
+    let (width, height) = (
+        area.width as u32 * cell_width,
+        area.height as u32 * cell_height,
+    );
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\" font-family=\"monospace\" font-size=\"{}\">\n",
+        cell_height * 4 / 5
+    );
+
+    // 同じ背景色が続くセルは1つの矩形にまとめる
+    for y in area.top()..area.bottom() {
+        let mut x = area.left();
+        while x < area.right() {
+            let start = x;
+            let (_, background) = pixel::colors(&buffer[(x, y)]);
+            while x < area.right() && pixel::colors(&buffer[(x, y)]).1 == background {
+                x += 1;
+            }
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                (start - area.left()) as u32 * cell_width,
+                (y - area.top()) as u32 * cell_height,
+                (x - start) as u32 * cell_width,
+                cell_height,
+                hex(background)
+            ));
+        }
+    }
+
+    // 全角文字の幅がフォントによってずれないよう、文字は1セルずつ位置を決めて置く
+    for (i, cell) in buffer.content.iter().enumerate() {
+        if cell.skip || cell.symbol().trim().is_empty() {
+            continue;
+        }
+        let (x, y) = buffer.pos_of(i);
+        let (foreground, _) = pixel::colors(cell);
+        let mut attributes = String::new();
+        if cell.modifier.contains(Modifier::BOLD) {
+            attributes.push_str(" font-weight=\"bold\"");
+        }
+        if cell.modifier.contains(Modifier::UNDERLINED) {
+            attributes.push_str(" text-decoration=\"underline\"");
+        } else if cell.modifier.contains(Modifier::CROSSED_OUT) {
+            attributes.push_str(" text-decoration=\"line-through\"");
+        }
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" fill=\"{}\"{}>{}</text>\n",
+            (x - area.left()) as u32 * cell_width,
+            // ベースラインはセルの下から1/5のあたり
+            (y - area.top()) as u32 * cell_height + cell_height * 4 / 5,
+            hex(foreground),
+            attributes,
+            escape(cell.symbol())
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn hex((r, g, b): Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,90 @@
+use std::io::{self, Stdout};
+use std::ops::{Deref, DerefMut};
+
+use anyhow::Result;
+use ratatui::backend::Backend;
+use ratatui::crossterm;
+use ratatui::prelude::CrosstermBackend;
+use ratatui::Terminal;
+
+use crate::pixel::PixelBackend;
+
+// TUI用に端末を初期化し、dropされたときに必ず元へ戻す
+pub struct TerminalGuard<B: Backend = CrosstermBackend<Stdout>> {
+    terminal: Terminal<B>,
+}
+
+// panicしたときもメッセージが崩れた画面に埋もれないよう、先に端末を戻してから表示する
+// 取得などの別スレッドのpanicではTUIが描き続けるので戻さない (Appがメインスレッドで終了させる)
+fn set_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            let _ = restore();
+        }
+        default_hook(info);
+    }));
+}
+
+impl TerminalGuard {
+    pub fn new() -> Result<Self> {
+        set_panic_hook();
+        crossterm::terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        crossterm::execute!(
+            stdout,
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::event::EnableMouseCapture
+        )?;
+
+        let backend = CrosstermBackend::new(stdout);
+        Ok(TerminalGuard {
+            terminal: Terminal::new(backend)?,
+        })
+    }
+}
+
+impl TerminalGuard<PixelBackend> {
+    // フレームバッファなどへ描くときも、キー入力は端末からrawモードで読む
+    pub fn pixel(backend: PixelBackend) -> Result<Self> {
+        set_panic_hook();
+        // PNGへの書き出しを端末の無い環境で試すこともあるので、rawモードにできなくても続ける
+        let _ = crossterm::terminal::enable_raw_mode();
+        Ok(TerminalGuard {
+            terminal: Terminal::new(backend)?,
+        })
+    }
+}
+
+// 何度呼んでも問題ないので、panic hookとdropの両方から呼ぶ
+pub fn restore() -> io::Result<()> {
+    crossterm::terminal::disable_raw_mode()?;
+    crossterm::execute!(
+        io::stdout(),
+        crossterm::terminal::LeaveAlternateScreen,
+        crossterm::event::DisableMouseCapture,
+        crossterm::cursor::Show
+    )
+}
+
+impl<B: Backend> Drop for TerminalGuard<B> {
+    fn drop(&mut self) {
+        if let Err(e) = restore() {
+            eprintln!("終了処理中にエラーが発生しました: {:?}", e);
+        }
+    }
+}
+
+impl<B: Backend> Deref for TerminalGuard<B> {
+    type Target = Terminal<B>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl<B: Backend> DerefMut for TerminalGuard<B> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
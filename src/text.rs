@@ -0,0 +1,186 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const ELLIPSIS: &str = "…";
+
+// 表示幅がwidthに収まるように切り詰める。切った場合は末尾を「…」にする
+pub fn ellipsize(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let limit = width - ELLIPSIS.width();
+    let mut result = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > limit {
+            break;
+        }
+        result.push(c);
+        used += w;
+    }
+    result + ELLIPSIS
+}
+
+// 折り返しの単位に分ける。英数字の単語はまとめ、日本語などは1文字ずつ区切れるようにする
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        if c.is_ascii_alphanumeric() || (c.is_ascii_punctuation() && start.is_some()) {
+            start.get_or_insert(i);
+            continue;
+        }
+        if let Some(s) = start.take() {
+            tokens.push(&text[s..i]);
+        }
+        tokens.push(&text[i..i + c.len_utf8()]);
+    }
+    if let Some(s) = start {
+        tokens.push(&text[s..]);
+    }
+    tokens
+}
+
+// 表示幅widthで折り返す。全角文字は幅2として数える
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    if width == 0 {
+        return Vec::new();
+    }
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut used = 0;
+    for token in tokens(text) {
+        let w = token.width();
+        if used + w > width && !line.is_empty() {
+            lines.push(line.trim_end().to_string());
+            line = String::new();
+            used = 0;
+            // 行頭の空白は捨てる
+            if token == " " {
+                continue;
+            }
+        }
+        if w <= width {
+            line.push_str(token);
+            used += w;
+            continue;
+        }
+        // 1行に収まらない長い単語は文字単位で割る
+        for c in token.chars() {
+            let cw = c.width().unwrap_or(0);
+            if used + cw > width {
+                lines.push(std::mem::take(&mut line));
+                used = 0;
+            }
+            line.push(c);
+            used += cw;
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+// 予定の枠 (width x height) に入れるタイトル、詳細、時刻の行を作る
+// 2行以上あれば時刻を最終行に分け、タイトルは残りの行で折り返す。入り切らない分は「…」にする
+// 詳細 (場所など) はタイトルを1行出したうえで余った行に1項目ずつ置く
+pub fn event_lines(
+    title: &str,
+    details: &[String],
+    time: &str,
+    width: usize,
+    height: usize,
+) -> Vec<String> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    if height == 1 {
+        let time_width = time.width();
+        // 時刻を残したうえでタイトルを最低2桁は出せるなら、タイトルの方を削る
+        if width >= time_width + 3 {
+            let title = ellipsize(title, width - time_width - 1);
+            return vec![format!("{} {}", title, time)];
+        }
+        return vec![ellipsize(&format!("{} {}", title, time), width)];
+    }
+
+    let details = &details[..details.len().min(height - 2)];
+    let title_height = height - 1 - details.len();
+    let mut lines = wrap(title, width);
+    if lines.len() > title_height {
+        lines.truncate(title_height);
+        let last = lines.pop().unwrap_or_default();
+        // 最終行の末尾に「…」を足して、続きがあることを示す
+        lines.push(ellipsize(&(last + ELLIPSIS), width));
+    }
+    lines.extend(details.iter().map(|detail| ellipsize(detail, width)));
+    lines.push(ellipsize(time, width));
+    lines
+}
+
+// 行の残りと空いた行を斜線で埋める
+pub fn hatch(lines: Vec<String>, width: usize, height: usize) -> Vec<String> {
+    let fill = |line: &str| format!("{}{}", line, "/".repeat(width.saturating_sub(line.width())));
+    let mut hatched: Vec<String> = lines.iter().map(|line| fill(line)).collect();
+    hatched.resize_with(height.max(hatched.len()), || fill(""));
+    hatched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_counts_double_width() {
+        assert_eq!(
+            wrap("週次定例ミーティング", 8),
+            vec!["週次定例", "ミーティ", "ング"]
+        );
+        assert_eq!(
+            wrap("Weekly sync with 営業チーム", 10),
+            vec!["Weekly", "sync with", "営業チーム"]
+        );
+    }
+
+    #[test]
+    fn test_ellipsize() {
+        assert_eq!(ellipsize("打ち合わせ", 10), "打ち合わせ");
+        assert_eq!(ellipsize("打ち合わせ", 7), "打ち合…");
+        assert_eq!(ellipsize("meeting", 4), "mee…");
+    }
+
+    #[test]
+    fn test_event_lines() {
+        // 1行しかないときは時刻を残してタイトルを削る
+        assert_eq!(
+            event_lines("プロジェクト定例", &[], "10:00~11:00", 21, 1),
+            vec!["プロジェ… 10:00~11:00"]
+        );
+        // 高さがあればタイトルを折り返し、時刻は最終行に置く
+        assert_eq!(
+            event_lines("プロジェクト定例", &[], "10:00~11:00", 12, 3),
+            vec!["プロジェクト", "定例", "10:00~11:00"]
+        );
+        assert_eq!(
+            event_lines("プロジェクト定例会議", &[], "10:00~11:00", 12, 2),
+            vec!["プロジェク…", "10:00~11:00"]
+        );
+        // 詳細はタイトルの下に入る分だけ出す
+        let details = [
+            "会議室A".to_string(),
+            "meet.google.com/abc-defg-hij".to_string(),
+        ];
+        assert_eq!(
+            event_lines("定例", &details, "10:00~11:00", 12, 3),
+            vec!["定例", "会議室A", "10:00~11:00"]
+        );
+        assert_eq!(
+            event_lines("定例", &details, "10:00~11:00", 12, 4),
+            vec!["定例", "会議室A", "meet.google…", "10:00~11:00"]
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::calendar::Calendar;
+use crate::config::{ThemeConfig, ThemeName};
+use crate::event::{EventModel, EventPhase};
+
+// 画面全体の配色。名前付きのテーマを元に、config.toml の [theme] で色を上書きできる
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub background: Color,
+    pub foreground: Color,
+    pub now_marker: Color,
+    // 1時間ごとの目盛り
+    pub grid: Color,
+    // Calendar::name() ごとの予定の背景色。無いカレンダーは Calendar::color() を使う
+    calendars: HashMap<String, Color>,
+    // Googleカレンダーで予定ごとに付けた色 (colorId) をカレンダーの色より優先するか
+    use_event_colors: bool,
+    // 終わった予定、進行中の予定、これからの予定に重ねるスタイル
+    past: Style,
+    current: Style,
+    future: Style,
+}
+
+impl Theme {
+    fn named(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Dark => Theme {
+                background: Color::Reset,
+                foreground: Color::Reset,
+                now_marker: Color::Yellow,
+                grid: Color::DarkGray,
+                calendars: HashMap::new(),
+                use_event_colors: true,
+                past: Style::default().add_modifier(Modifier::DIM),
+                current: Style::default().add_modifier(Modifier::BOLD),
+                future: Style::default(),
+            },
+            ThemeName::Light => Theme {
+                background: Color::Rgb(0xfa, 0xfa, 0xfa),
+                foreground: Color::Rgb(0x20, 0x20, 0x20),
+                now_marker: Color::Rgb(0xff, 0x8f, 0x00),
+                grid: Color::Rgb(0xc8, 0xc8, 0xc8),
+                calendars: Self::calendar_colors([
+                    Color::Rgb(0xef, 0x9a, 0x9a),
+                    Color::Rgb(0x90, 0xca, 0xf9),
+                    Color::Rgb(0xa5, 0xd6, 0xa7),
+                ]),
+                use_event_colors: true,
+                past: Style::default().add_modifier(Modifier::DIM),
+                current: Style::default().add_modifier(Modifier::BOLD),
+                future: Style::default(),
+            },
+            ThemeName::HighContrast => Theme {
+                background: Color::Black,
+                foreground: Color::White,
+                now_marker: Color::LightYellow,
+                grid: Color::Gray,
+                calendars: Self::calendar_colors([
+                    Color::LightRed,
+                    Color::LightCyan,
+                    Color::LightGreen,
+                ]),
+                use_event_colors: true,
+                past: Style::default().add_modifier(Modifier::CROSSED_OUT),
+                current: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                future: Style::default(),
+            },
+            // e-inkは中間色が出ないので、白地に黒の反転だけで区別する
+            // 斜体はピクセルの描画では出せないので、終わった予定は取り消し線で示す
+            ThemeName::Mono => Theme {
+                background: Color::White,
+                foreground: Color::Black,
+                now_marker: Color::Black,
+                grid: Color::Black,
+                calendars: Self::calendar_colors([Color::Black, Color::Black, Color::Black]),
+                use_event_colors: false,
+                past: Style::default().add_modifier(Modifier::CROSSED_OUT),
+                current: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                future: Style::default(),
+            },
+        }
+    }
+
+    fn calendar_colors(colors: [Color; 3]) -> HashMap<String, Color> {
+        [Calendar::Primary, Calendar::Private, Calendar::University]
+            .iter()
+            .zip(colors)
+            .map(|(calendar, color)| (calendar.name().to_string(), color))
+            .collect()
+    }
+
+    pub fn from_config(config: &ThemeConfig) -> Self {
+        let mut theme = Theme::named(config.name);
+        if let Some(color) = config.background {
+            theme.background = color;
+        }
+        if let Some(color) = config.foreground {
+            theme.foreground = color;
+        }
+        if let Some(color) = config.now_marker {
+            theme.now_marker = color;
+        }
+        if let Some(color) = config.grid {
+            theme.grid = color;
+        }
+        if let Some(use_event_colors) = config.use_event_colors {
+            theme.use_event_colors = use_event_colors;
+        }
+        theme.calendars.extend(
+            config
+                .calendars
+                .iter()
+                .map(|(name, color)| (name.clone(), *color)),
+        );
+        theme
+    }
+
+    pub fn base(&self) -> Style {
+        Style::default().bg(self.background).fg(self.foreground)
+    }
+
+    pub fn calendar_color(&self, calendar: &Calendar) -> Color {
+        self.calendars
+            .get(calendar.name())
+            .copied()
+            .unwrap_or_else(|| calendar.color())
+    }
+
+    // [[rules]] で付けた色、予定ごとの色、カレンダーの色の順に優先する
+    pub fn event_color(&self, event: &EventModel) -> Color {
+        if let Some(color) = event.rule_color() {
+            return color;
+        }
+        match event.event_color() {
+            Some(color) if self.use_event_colors => color,
+            _ => self.calendar_color(event.calendar()),
+        }
+    }
+
+    // 予定の背景色に対して読みやすい文字色を選び、時間帯ごとのスタイルを重ねる
+    pub fn event_style(&self, background: Color, phase: EventPhase) -> Style {
+        let phase_style = match phase {
+            EventPhase::Past => self.past,
+            EventPhase::Current => self.current,
+            EventPhase::Future => self.future,
+        };
+        Style::default()
+            .bg(background)
+            .fg(contrast_foreground(background, self.foreground))
+            .patch(phase_style)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::named(ThemeName::default())
+    }
+}
+
+// 背景色の相対輝度から黒と白の読みやすい方を返す。色が分からないときはfallbackを使う
+fn contrast_foreground(background: Color, fallback: Color) -> Color {
+    match rgb(background) {
+        Some((r, g, b)) => {
+            let linear = |c: u8| {
+                let c = c as f64 / 255.0;
+                if c <= 0.03928 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            };
+            let luminance = 0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b);
+            // 白との対比と黒との対比が等しくなる輝度 (約0.179) を境に切り替える
+            if luminance > 0.179 {
+                Color::Black
+            } else {
+                Color::White
+            }
+        }
+        None => fallback,
+    }
+}
+
+// 端末の色をおおよそのRGBに直す。名前付きの色はxtermの既定値を使う
+pub fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    Some(match color {
+        Color::Reset => return None,
+        Color::Black => (0x00, 0x00, 0x00),
+        Color::Red => (0xcd, 0x00, 0x00),
+        Color::Green => (0x00, 0xcd, 0x00),
+        Color::Yellow => (0xcd, 0xcd, 0x00),
+        Color::Blue => (0x00, 0x00, 0xee),
+        Color::Magenta => (0xcd, 0x00, 0xcd),
+        Color::Cyan => (0x00, 0xcd, 0xcd),
+        Color::Gray => (0xe5, 0xe5, 0xe5),
+        Color::DarkGray => (0x7f, 0x7f, 0x7f),
+        Color::LightRed => (0xff, 0x00, 0x00),
+        Color::LightGreen => (0x00, 0xff, 0x00),
+        Color::LightYellow => (0xff, 0xff, 0x00),
+        Color::LightBlue => (0x5c, 0x5c, 0xff),
+        Color::LightMagenta => (0xff, 0x00, 0xff),
+        Color::LightCyan => (0x00, 0xff, 0xff),
+        Color::White => (0xff, 0xff, 0xff),
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(index) => match index {
+            0..=15 => return rgb(ANSI_COLORS[index as usize]),
+            // 16~231 は 6x6x6 のカラーキューブ
+            16..=231 => {
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                let i = index - 16;
+                (level(i / 36), level((i / 6) % 6), level(i % 6))
+            }
+            // 232~255 はグレースケール
+            232..=255 => {
+                let v = 8 + (index - 232) * 10;
+                (v, v, v)
+            }
+        },
+    })
+}
+
+const ANSI_COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contrast_foreground() {
+        assert_eq!(
+            contrast_foreground(Color::Yellow, Color::Reset),
+            Color::Black
+        );
+        assert_eq!(contrast_foreground(Color::Blue, Color::Reset), Color::White);
+        assert_eq!(
+            contrast_foreground(Color::Rgb(0xa5, 0xd6, 0xa7), Color::Reset),
+            Color::Black
+        );
+        // 256色のグレースケールの暗い側と明るい側
+        assert_eq!(
+            contrast_foreground(Color::Indexed(233), Color::Reset),
+            Color::White
+        );
+        assert_eq!(
+            contrast_foreground(Color::Indexed(254), Color::Reset),
+            Color::Black
+        );
+        assert_eq!(contrast_foreground(Color::Reset, Color::Gray), Color::Gray);
+    }
+
+    #[test]
+    fn test_event_color_overrides_calendar_color() {
+        let event = EventModel::new(Default::default(), Calendar::Private);
+        let colored = event
+            .clone()
+            .with_event_color(Some(Color::Rgb(0x7a, 0xe7, 0xbf)));
+
+        let dark = Theme::default();
+        assert_eq!(dark.event_color(&event), Color::Blue);
+        assert_eq!(dark.event_color(&colored), Color::Rgb(0x7a, 0xe7, 0xbf));
+
+        // monoテーマでは予定ごとの色を使わない
+        let mono = Theme::named(ThemeName::Mono);
+        assert_eq!(mono.event_color(&colored), Color::Black);
+    }
+}
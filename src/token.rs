@@ -5,12 +5,14 @@ use std::net::TcpListener;
 use anyhow::Result;
 use oauth2::basic::BasicClient;
 use oauth2::url::Url;
-use oauth2::{reqwest, RefreshToken, RevocationUrl};
+use oauth2::{reqwest, HttpClientError, RefreshToken, RequestTokenError, RevocationUrl};
 use oauth2::{
     AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge, RedirectUrl,
     Scope, TokenResponse, TokenUrl,
 };
 
+use crate::error::FetchError;
+use crate::metrics::Metrics;
 use crate::OAuthClient;
 
 pub struct Token {
@@ -18,6 +20,7 @@ pub struct Token {
     pub refresh_token: String,
     auth_client: OAuthClient,
     http_client: reqwest::blocking::Client,
+    metrics: Metrics,
 }
 
 impl Token {
@@ -113,17 +116,32 @@ impl Token {
         Ok(())
     }
 
-    pub fn refresh(&mut self) -> Result<()> {
+    // 通信できなかったときは、認証の失敗 (リフレッシュトークンの失効など) と区別して返す
+    // 失敗は呼び出し元の取得の失敗として数えるので、ここでは成功した回数だけ数える
+    pub fn refresh(&mut self) -> Result<(), FetchError> {
         let token_response = self
             .auth_client
             .exchange_refresh_token(&RefreshToken::new(self.refresh_token.clone()))
-            .request(&self.http_client)?;
+            .request(&self.http_client)
+            .map_err(|e| match e {
+                RequestTokenError::Request(HttpClientError::Reqwest(e)) => FetchError::Network(e),
+                RequestTokenError::Request(e) => FetchError::Network(Box::new(e)),
+                e => FetchError::Auth(format!("token refresh failed: {}", e)),
+            })?;
 
         self.access_token = token_response.access_token().secret().clone();
+        self.metrics.count_token_refresh();
         Ok(())
     }
 
-    pub fn new(client_id: String, client_secret: String) -> Result<Self> {
+    // interactiveでなければ、tokens.jsonが無いときにブラウザでの認証を始めずにエラーにする
+    // (--output で使うときに標準出力へURLを出したり、リダイレクトを待って止まったりしないように)
+    pub fn new(
+        client_id: String,
+        client_secret: String,
+        interactive: bool,
+        metrics: Metrics,
+    ) -> Result<Self> {
         let auth_client = BasicClient::new(ClientId::new(client_id))
             .set_client_secret(ClientSecret::new(client_secret))
             .set_auth_uri(
@@ -155,10 +173,17 @@ impl Token {
                     refresh_token,
                     auth_client,
                     http_client,
+                    metrics,
                 };
                 token.refresh()?;
                 token
             }
+            Err(_) if !interactive => {
+                return Err(FetchError::Auth(
+                    "tokens.json is not found; run without --output once to authorize".to_string(),
+                )
+                .into());
+            }
             Err(_) => {
                 let (access_token, refresh_token) =
                     Token::fetch_tokens(auth_client.clone(), http_client.clone())?;
@@ -167,6 +192,7 @@ impl Token {
                     refresh_token,
                     auth_client,
                     http_client,
+                    metrics,
                 }
             }
         };
@@ -0,0 +1,538 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::{Asia::Tokyo, Tz};
+use ratatui::backend::Backend;
+use ratatui::layout::{Position, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, BorderType, Borders, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use crate::config::LayoutMode;
+use crate::detail::DetailPopup;
+use crate::event::{EventPhase, EventView};
+use crate::text;
+use crate::theme::Theme;
+
+// 1行目は表示中の日付のヘッダー
+const HEADER_HEIGHT: u16 = 1;
+
+// 1日分の列。複数日表示のときは縦向きなら横に、横向きなら縦に並べる
+#[derive(Clone)]
+pub struct DayColumn {
+    pub date: NaiveDate,
+    pub events: Vec<EventView>,
+    // 今日の列だけ現在時刻の線を出す
+    pub now: Option<DateTime<Tz>>,
+    pub selected: Option<usize>,
+}
+
+// 時間軸の向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Orientation {
+    // 上から下へ時間が流れる (縦置きのディスプレイ向け)
+    Vertical,
+    // 左から右へ時間が流れる (横置きのディスプレイ向け)
+    Horizontal,
+}
+
+impl Orientation {
+    fn resolve(mode: LayoutMode, area: Rect) -> Self {
+        match mode {
+            LayoutMode::Vertical => Orientation::Vertical,
+            LayoutMode::Horizontal => Orientation::Horizontal,
+            // 端末の1文字はおおよそ縦横比2:1なので、桁数が行数の2倍を超えれば横長の画面
+            LayoutMode::Auto if area.width > area.height * 2 => Orientation::Horizontal,
+            LayoutMode::Auto => Orientation::Vertical,
+        }
+    }
+}
+
+// ヘッダーを除いた領域を日数分の列に分ける
+fn column_areas(area: Rect, days: usize, orientation: Orientation) -> Vec<Rect> {
+    let body = Rect {
+        y: area.y + HEADER_HEIGHT,
+        height: area.height.saturating_sub(HEADER_HEIGHT),
+        ..area
+    };
+    let days = days.max(1) as u16;
+    match orientation {
+        Orientation::Vertical => {
+            let width = body.width / days;
+            (0..days)
+                .map(|i| Rect {
+                    x: body.x + i * width,
+                    width,
+                    ..body
+                })
+                .collect()
+        }
+        Orientation::Horizontal => {
+            let height = body.height / days;
+            (0..days)
+                .map(|i| Rect {
+                    y: body.y + i * height,
+                    height,
+                    ..body
+                })
+                .collect()
+        }
+    }
+}
+
+// 複数日表示のときは列の先頭1行に日付を出すので、その下がタイムライン
+fn timeline_area(column_area: Rect, with_label: bool) -> Rect {
+    if with_label {
+        Rect {
+            y: column_area.y + 1,
+            height: column_area.height.saturating_sub(1),
+            ..column_area
+        }
+    } else {
+        column_area
+    }
+}
+
+// 0時からslots (30分単位) 進んだ位置の、タイムラインの先頭からの行数 (横向きのときは桁数)
+// 48行以上あれば30分に同じ行数を割り当て、足りなければ1日分を詰めて収める
+fn time_offset(area: Rect, orientation: Orientation, slots: u16) -> u16 {
+    let length = match orientation {
+        Orientation::Vertical => area.height,
+        Orientation::Horizontal => area.width,
+    };
+    match length / 48 {
+        0 => (slots as u32 * length as u32 / 48) as u16,
+        unit => slots * unit,
+    }
+}
+
+// 縦向きは左端1桁、横向きは上端1行を現在時刻の印のために空けておく
+fn event_rect(area: Rect, event: &EventView, orientation: Orientation) -> Rect {
+    let start = time_offset(area, orientation, event.start);
+    let end = time_offset(area, orientation, event.start + event.height);
+    // 詰めて表示するときも短い予定が消えないよう1行 (1桁) は取り、はみ出す分は切る
+    let length = (end - start).max(1);
+    let rect = match orientation {
+        Orientation::Vertical => Rect {
+            x: area.x + 1,
+            y: area.y + start,
+            width: area.width.saturating_sub(1),
+            height: length,
+        },
+        Orientation::Horizontal => Rect {
+            x: area.x + start,
+            y: area.y + 1,
+            width: length,
+            height: area.height.saturating_sub(1),
+        },
+    };
+    rect.intersection(area)
+}
+
+fn now_marker_rect(area: Rect, now: DateTime<Tz>, orientation: Orientation) -> Rect {
+    let offset = time_offset(
+        area,
+        orientation,
+        EventView::date_time_to_height(now, &Tokyo),
+    );
+    match orientation {
+        Orientation::Vertical => Rect::new(area.x, area.y + offset, 1, 1),
+        Orientation::Horizontal => Rect::new(area.x + offset, area.y, 1, 1),
+    }
+}
+
+// クリックされた位置にある予定を (列, 列内のインデックス) で返す
+pub fn event_at(
+    area: Rect,
+    layout: LayoutMode,
+    columns: &[Vec<EventView>],
+    column: u16,
+    row: u16,
+) -> Option<(usize, usize)> {
+    let orientation = Orientation::resolve(layout, area);
+    let position = Position::new(column, row);
+    let with_label = columns.len() > 1;
+    column_areas(area, columns.len(), orientation)
+        .into_iter()
+        .zip(columns)
+        .enumerate()
+        .find_map(|(i, (column_area, events))| {
+            let area = timeline_area(column_area, with_label);
+            events
+                .iter()
+                .position(|event| event_rect(area, event, orientation).contains(position))
+                .map(|j| (i, j))
+        })
+}
+
+// 時刻は呼び出し側から渡す。画像の書き出しやテストで表示を固定できるようにするため
+#[allow(clippy::too_many_arguments)]
+pub fn render_ui<B: Backend>(
+    terminal: &mut Terminal<B>,
+    layout: LayoutMode,
+    theme: &Theme,
+    now: DateTime<Tz>,
+    header: String,
+    reminder: Option<String>,
+    countdown_minutes: i64,
+    columns: Vec<DayColumn>,
+    popup: Option<DetailPopup>,
+) -> Result<()> {
+    // 毎回clearせず、ratatuiの差分描画に任せてちらつきを抑える
+    terminal.draw(|frame| {
+        let area = frame.area();
+        let orientation = Orientation::resolve(layout, area);
+        frame.render_widget(Block::default().style(theme.base()), area);
+        // リマインダーがあるときはヘッダーを反転させて目立たせる
+        let header = match reminder {
+            Some(reminder) => Paragraph::new(format!("リマインダー: {}", reminder)).style(
+                theme
+                    .base()
+                    .add_modifier(Modifier::REVERSED | Modifier::BOLD),
+            ),
+            None => Paragraph::new(header),
+        };
+        frame.render_widget(
+            header,
+            Rect {
+                height: HEADER_HEIGHT.min(area.height),
+                ..area
+            },
+        );
+
+        let with_label = columns.len() > 1;
+        let column_areas = column_areas(area, columns.len(), orientation);
+        for (column_area, column) in column_areas.into_iter().zip(columns) {
+            if with_label {
+                frame.render_widget(
+                    Paragraph::new(column.date.format(" %m/%d (%a)").to_string()),
+                    Rect {
+                        height: 1.min(column_area.height),
+                        ..column_area
+                    },
+                );
+            }
+            render_timeline(
+                frame,
+                timeline_area(column_area, with_label),
+                column,
+                orientation,
+                theme,
+                now,
+                countdown_minutes,
+            );
+        }
+
+        if let Some(popup) = popup {
+            popup.render(frame, theme.base());
+        }
+    })?;
+    Ok(())
+}
+
+// 画面を消している間は全体を黒く塗る
+pub fn render_blank<B: Backend>(terminal: &mut Terminal<B>) -> Result<()> {
+    terminal.draw(|frame| {
+        frame.render_widget(
+            Block::default().style(Style::default().bg(Color::Black)),
+            frame.area(),
+        );
+    })?;
+    Ok(())
+}
+
+// 1時間ごとの目盛りと時刻。予定はこの上に重ねて描く
+fn render_hour_grid(frame: &mut Frame, area: Rect, orientation: Orientation, theme: &Theme) {
+    let style = theme.base().fg(theme.grid);
+    // 詰めて表示するときは、前の目盛りと重なる時刻を飛ばす。横向きは時刻の2桁と間の1桁を空ける
+    let gap = match orientation {
+        Orientation::Vertical => 1,
+        Orientation::Horizontal => 3,
+    };
+    let mut next_free = 0;
+    for hour in 0..24 {
+        let offset = time_offset(area, orientation, hour * 2);
+        if offset < next_free {
+            continue;
+        }
+        next_free = offset + gap;
+        let label = format!("{:02}", hour);
+        match orientation {
+            // 縦向きは時刻の行に横線を引く
+            Orientation::Vertical => {
+                let rect = Rect {
+                    x: area.x + 1,
+                    y: area.y + offset,
+                    width: area.width.saturating_sub(1),
+                    height: 1,
+                };
+                let line = format!(
+                    "{}{}",
+                    label,
+                    "─".repeat((rect.width as usize).saturating_sub(label.len()))
+                );
+                frame.render_widget(Paragraph::new(line).style(style), rect);
+            }
+            // 横向きは時刻の桁に縦線を引き、上端の行に時刻を出す
+            Orientation::Horizontal => {
+                let x = area.x + offset;
+                frame.render_widget(
+                    Paragraph::new(label).style(style),
+                    Rect::new(x, area.y, 2.min(area.width - offset), 1),
+                );
+                let height = area.height.saturating_sub(1);
+                frame.render_widget(
+                    Paragraph::new(vec!["│".into(); height as usize]).style(style),
+                    Rect::new(x, area.y + 1, 1, height),
+                );
+            }
+        }
+    }
+}
+
+// 1日分の24時間タイムラインを描画する
+fn render_timeline(
+    frame: &mut Frame,
+    area: Rect,
+    column: DayColumn,
+    orientation: Orientation,
+    theme: &Theme,
+    now: DateTime<Tz>,
+    countdown_minutes: i64,
+) {
+    render_hour_grid(frame, area, orientation, theme);
+
+    // 今日の列では、次に始まる予定に開始までの残り時間を出す
+    let next = column.now.and_then(|now| {
+        column
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| event.phase(now) == EventPhase::Future)
+            .min_by_key(|(_, event)| event.start_time)
+            .map(|(i, _)| i)
+    });
+
+    // render events
+    for (i, event) in column.events.iter().enumerate() {
+        let phase = event.phase(now);
+        let mut style = theme.event_style(event.color, phase);
+        if column.selected == Some(i) {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        // 進行中の予定は左端に太線を引いて目立たせる。高さ1行の予定でも出せるよう左だけにする
+        let block = if phase == EventPhase::Current {
+            Block::default()
+                .borders(Borders::LEFT)
+                .border_type(BorderType::Thick)
+        } else {
+            Block::default().borders(Borders::NONE)
+        };
+        let mut time = event.time.clone();
+        if next == Some(i) {
+            time.push_str(&format!(
+                " ({})",
+                countdown(event.start_time - now, countdown_minutes)
+            ));
+        }
+        let rect = event_rect(area, event, orientation);
+        // 太線の分だけ文字を置ける幅が狭くなる
+        let width = block.inner(rect).width;
+        let lines = text::event_lines(
+            &event.title,
+            &event.details,
+            &time,
+            width.into(),
+            rect.height.into(),
+        );
+        let lines = if event.tentative {
+            text::hatch(lines, width.into(), rect.height.into())
+        } else {
+            lines
+        };
+        frame.render_widget(
+            Paragraph::new(lines.join("\n")).block(block.style(style)),
+            rect,
+        );
+    }
+
+    // render now line
+    if let Some(now) = column.now {
+        let marker = match orientation {
+            Orientation::Vertical => ">",
+            Orientation::Horizontal => "v",
+        };
+        frame.render_widget(
+            Paragraph::new(marker).block(
+                Block::default()
+                    .borders(Borders::NONE)
+                    .style(Style::default().bg(theme.now_marker).fg(theme.background)),
+            ),
+            now_marker_rect(area, now, orientation),
+        );
+    }
+}
+
+// 次の予定までの残り時間の表示。stepの分単位で切り上げる (電子ペーパーでは5分など)
+fn countdown(remaining: chrono::TimeDelta, step: i64) -> String {
+    // 秒は切り上げて、開始直前でも「あと0分」にならないようにする
+    let step = step.max(1);
+    let minutes = (remaining.num_seconds() + step * 60 - 1) / (step * 60) * step;
+    if minutes >= 60 {
+        format!("あと{}時間{}分", minutes / 60, minutes % 60)
+    } else {
+        format!("あと{}分", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use ratatui::buffer::Buffer;
+
+    fn view(start: u16, height: u16) -> EventView {
+        let time = Tokyo.with_ymd_and_hms(2023, 10, 1, 0, 0, 0).unwrap();
+        EventView {
+            title: String::new(),
+            time: String::new(),
+            details: Vec::new(),
+            tentative: false,
+            height,
+            color: Color::Red,
+            start,
+            start_time: time,
+            end_time: time,
+        }
+    }
+
+    #[test]
+    fn test_orientation_from_aspect_ratio() {
+        let portrait = Rect::new(0, 0, 60, 100);
+        let landscape = Rect::new(0, 0, 200, 50);
+        assert_eq!(
+            Orientation::resolve(LayoutMode::Auto, portrait),
+            Orientation::Vertical
+        );
+        assert_eq!(
+            Orientation::resolve(LayoutMode::Auto, landscape),
+            Orientation::Horizontal
+        );
+        assert_eq!(
+            Orientation::resolve(LayoutMode::Vertical, landscape),
+            Orientation::Vertical
+        );
+    }
+
+    #[test]
+    fn test_event_rect() {
+        // 09:00~10:30
+        let event = view(18, 3);
+
+        let area = Rect::new(0, 1, 40, 96);
+        assert_eq!(
+            event_rect(area, &event, Orientation::Vertical),
+            Rect::new(1, 37, 39, 6)
+        );
+
+        let area = Rect::new(0, 1, 192, 10);
+        assert_eq!(
+            event_rect(area, &event, Orientation::Horizontal),
+            Rect::new(72, 2, 12, 9)
+        );
+    }
+
+    // 18:00~19:00の予定を小さな端末に描く
+    fn render_small(layout: LayoutMode, width: u16, height: u16) -> Buffer {
+        let mut terminal =
+            Terminal::new(ratatui::backend::TestBackend::new(width, height)).unwrap();
+        let now = Tokyo.with_ymd_and_hms(2023, 10, 1, 8, 0, 0).unwrap();
+        // 18:00~19:00
+        let mut event = view(36, 2);
+        event.title = "Dinner".to_string();
+        let column = DayColumn {
+            date: now.date_naive(),
+            events: vec![event],
+            now: None,
+            selected: None,
+        };
+        render_ui(
+            &mut terminal,
+            layout,
+            &Theme::default(),
+            now,
+            String::new(),
+            None,
+            1,
+            vec![column],
+            None,
+        )
+        .unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    // 行を端から順につないだ画面の文字
+    fn screen_text(buffer: &Buffer) -> String {
+        buffer.content.iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn test_small_vertical() {
+        // 48行に満たない端末でも1日分を詰めて表示する
+        let text = screen_text(&render_small(LayoutMode::Vertical, 80, 30));
+        assert!(text.contains("Dinner"));
+        assert!(text.contains("12─"));
+        assert!(text.contains("23─"));
+
+        let area = Rect::new(0, 1, 80, 29);
+        assert_eq!(
+            event_rect(area, &view(36, 2), Orientation::Vertical),
+            Rect::new(1, 22, 79, 1)
+        );
+        assert_eq!(
+            event_rect(area, &view(47, 1), Orientation::Vertical),
+            Rect::new(1, 29, 79, 1)
+        );
+    }
+
+    #[test]
+    fn test_small_horizontal() {
+        // 48桁に満たない端末でも1日分を詰めて表示する
+        let buffer = render_small(LayoutMode::Horizontal, 40, 12);
+        // 予定は1桁の幅になるので、タイトルは1文字ずつ折り返される
+        assert_eq!(buffer[(30, 2)].symbol(), "D");
+        assert_eq!(buffer[(30, 3)].symbol(), "i");
+        let text = screen_text(&buffer);
+        assert!(text.contains("00"));
+        assert!(text.contains("12"));
+
+        let area = Rect::new(0, 1, 40, 11);
+        assert_eq!(
+            event_rect(area, &view(36, 2), Orientation::Horizontal),
+            Rect::new(30, 2, 1, 10)
+        );
+        assert_eq!(
+            event_rect(area, &view(18, 6), Orientation::Horizontal),
+            Rect::new(15, 2, 5, 10)
+        );
+    }
+
+    #[test]
+    fn test_countdown() {
+        assert_eq!(countdown(chrono::TimeDelta::minutes(12), 1), "あと12分");
+        assert_eq!(countdown(chrono::TimeDelta::seconds(30), 1), "あと1分");
+        assert_eq!(
+            countdown(chrono::TimeDelta::minutes(95), 1),
+            "あと1時間35分"
+        );
+        // 5分単位なら11分から15分までは同じ表示になり、その間は描き直さずに済む
+        assert_eq!(countdown(chrono::TimeDelta::minutes(11), 5), "あと15分");
+        assert_eq!(
+            countdown(chrono::TimeDelta::seconds(15 * 60), 5),
+            "あと15分"
+        );
+        assert_eq!(
+            countdown(chrono::TimeDelta::seconds(15 * 60 + 1), 5),
+            "あと20分"
+        );
+    }
+}